@@ -1,4 +1,11 @@
 use crate::prelude::*;
+use std::io::{Read, Write, BufWriter};
+use std::process::{Child, Command, Stdio};
+use memmap2::{Mmap, MmapOptions};
+
+
+
+pub const FILTER_COMMAND_TIMEOUT: Duration = Duration::from_secs(10);
 
 
 
@@ -33,7 +40,10 @@ pub fn process_task (current_task: ProgramTask, program_data: &ProgramData) -> R
 
     match current_task {
         ProgramTask::LoadFile{file_path, switch_to_this} => load_file(&file_path, program_data)?,
-        ProgramTask::SaveFile{file_num, file_path} => save_file(&file_path, program_data)?,
+        ProgramTask::SaveFile{file_num, file_path} => save_file(file_num, &file_path, program_data)?,
+        ProgramTask::ReloadWithEncoding{file_num, encoding} => reload_file(file_num, encoding, program_data)?,
+        ProgramTask::IndexLines{line_index} => index_lines(line_index, program_data),
+        ProgramTask::FilterText{file_num, file_path, command, ranges} => filter_text(file_num, &file_path, &command, ranges, program_data)?,
     }
 
     Ok(())
@@ -46,15 +56,7 @@ pub fn process_task (current_task: ProgramTask, program_data: &ProgramData) -> R
 pub fn load_file (file_path: &str, program_data: &ProgramData) -> Result<(), ProgramError> {
     println!("Loading files {file_path}");
 
-    let contents = match fs::read_to_string(file_path) {
-        Ok(v) => v,
-        Err(error) => return err(RawProgramError::CouldNotLoadFile {
-            file_path: file_path.to_string(),
-            source: error,
-        }),
-    };
-    let contents = fns::split_lines(&contents);
-    let new_file = File::new(file_path.to_string(), contents);
+    let new_file = read_file(file_path, None, program_data)?;
     program_data.files.write().push(new_file);
 
     let mut curent_file = program_data.current_file_num.write();
@@ -69,7 +71,434 @@ pub fn load_file (file_path: &str, program_data: &ProgramData) -> Result<(), Pro
 
 
 
-pub fn save_file (file_path: &str, program_data: &ProgramData) -> Result<(), ProgramError> {
-    println!("wip: save file {file_path}");
+// re-reads a file from disk with the given encoding, which discards the undo history, so files with unsaved changes are left alone
+pub fn reload_file (file_num: usize, encoding: TextEncoding, program_data: &ProgramData) -> Result<(), ProgramError> {
+
+    let files = program_data.files.read();
+    let Some(file) = files.get(file_num) else {
+        return err(RawProgramError::InvalidFileIndex {
+            file_index: file_num,
+            num_of_files: files.len(),
+        });
+    };
+    // the file could have been edited after this task was added
+    if file.history.current_step != file.history.saved_step {
+        println!("Warning: {} has unsaved changes, so it wasn't reloaded", file.path);
+        return Ok(());
+    }
+    let file_path = file.path.to_string();
+    drop(files);
+
+    println!("Reloading file {file_path} as {encoding:?}");
+    let new_file = read_file(&file_path, Some(encoding), program_data)?;
+    let mut files = program_data.files.write();
+    match files.get_mut(file_num) {
+        Some(file) if file.path == file_path && file.history.current_step == file.history.saved_step => *file = new_file,
+        _ => println!("Warning: {file_path} was closed or edited while reloading it, so it wasn't reloaded"),
+    }
+
     Ok(())
 }
+
+
+
+// the encoding is detected if it isn't given
+pub fn read_file (file_path: &str, encoding: Option<TextEncoding>, program_data: &ProgramData) -> Result<File, ProgramError> {
+
+    let large_file_threshold = program_data.settings.read().as_ref().map_or(u64::MAX, |settings| settings.large_file_threshold);
+    let file_size = fs::metadata(file_path).map_or(0, |metadata| metadata.len());
+    if encoding.is_none() && file_size > large_file_threshold {
+        if let Some(new_file) = read_file_lazily(file_path, program_data)? {return Ok(new_file);}
+    }
+
+    let bytes = match fs::read(file_path) {
+        Ok(v) => v,
+        Err(error) => return err(RawProgramError::CouldNotLoadFile {
+            file_path: file_path.to_string(),
+            source: error,
+        }),
+    };
+    let contents_hash = undo_journal::hash_bytes(&bytes);
+    let detected_encoding = encoding.is_none();
+
+    if detected_encoding && file_encoding::is_binary(&bytes) {
+        println!("File {file_path} is binary, opening in hex view");
+        let mut new_file = File::new(file_path.to_string(), TextBuffer::new());
+        new_file.saved_contents_hash = Some(contents_hash);
+        new_file.hex_view = Some(HexView::new(HexBytes::Loaded(bytes)));
+        return Ok(new_file);
+    }
+
+    let encoding = encoding.unwrap_or_else(|| file_encoding::detect_encoding(&bytes));
+    let contents = file_encoding::decode(&bytes, encoding);
+    drop(bytes);
+
+    let (line_ending, has_mixed_line_endings) = fns::get_line_endings(&contents);
+    if has_mixed_line_endings {
+        println!("Warning: file {file_path} has mixed line endings, they will be saved as {line_ending:?}");
+    }
+    let mut new_file = File::new(file_path.to_string(), TextBuffer::from_text(&contents.replace("\r\n", "\n")));
+    set_file_indentation(&mut new_file, &contents, program_data);
+    new_file.saved_contents_hash = Some(contents_hash);
+    new_file.line_ending = line_ending;
+    new_file.has_mixed_line_endings = has_mixed_line_endings;
+    new_file.encoding = encoding;
+
+    // a history from a different encoding wouldn't line up with the decoded text
+    if detected_encoding {
+        match undo_journal::load_journal(file_path, contents_hash) {
+            Ok(Some(history)) => new_file.history = history,
+            Ok(None) => {}
+            Err(error) => {
+                println!("Warning: could not load undo history for {file_path}, it will be discarded");
+                println!("Error: {:?}", error);
+            }
+        }
+    }
+
+    Ok(new_file)
+}
+
+
+
+// returns None if the file can't be loaded lazily, which only works with binary files and utf-8 or latin-1 text
+pub fn read_file_lazily (file_path: &str, program_data: &ProgramData) -> Result<Option<File>, ProgramError> {
+
+    let to_load_error = |error: IoError| ProgramError::from(RawProgramError::CouldNotLoadFile {
+        file_path: file_path.to_string(),
+        source: error,
+    });
+    let opened_file = fs::File::open(file_path).map_err(to_load_error)?;
+    // this could break if another program changes the file while it's open, but that's the cost of not loading everything
+    let mmap = unsafe {Mmap::map(&opened_file)}.map_err(to_load_error)?;
+
+    let start = &mmap[..mmap.len().min(64 * 1024)];
+    if file_encoding::is_binary(start) {
+        // the bytes are mapped copy-on-write, so edits don't change the file until it's saved
+        let hex_mmap = unsafe {MmapOptions::new().map_copy(&opened_file)}.map_err(to_load_error)?;
+        println!("File {file_path} is large and binary, opening it lazily in hex view");
+        let mut new_file = File::new(file_path.to_string(), TextBuffer::new());
+        new_file.hex_view = Some(HexView::new(HexBytes::Mapped(hex_mmap)));
+        return Ok(Some(new_file));
+    }
+    let start_is_cut_off_utf8 = std::str::from_utf8(start).err().is_some_and(|error| error.error_len().is_none());
+    let encoding = match file_encoding::detect_encoding(start) {
+        TextEncoding::Utf8 => TextEncoding::Utf8,
+        TextEncoding::Latin1 if start_is_cut_off_utf8 => TextEncoding::Utf8,
+        TextEncoding::Latin1 => TextEncoding::Latin1,
+        _ => return Ok(None),
+    };
+    let start_text = String::from_utf8_lossy(start).to_string();
+    let (line_ending, _) = fns::get_line_endings(&start_text);
+    println!("File {file_path} is large, loading it lazily");
+
+    // the first lines are indexed right away, otherwise an edit before the first chunk is done would be put at the end of the file
+    let line_index = Arc::new(LineIndex::new(mmap, encoding == TextEncoding::Utf8));
+    let mut is_indexed = line_index.index_next_chunk();
+    while !is_indexed && line_index.get_line_count() == 0 {
+        is_indexed = line_index.index_next_chunk();
+    }
+    if !is_indexed {
+        program_data.tasks.write().push(ProgramTask::IndexLines {line_index: line_index.clone()});
+    }
+    if *line_index.has_invalid_utf8.read() {
+        println!("Warning: {file_path} has invalid utf-8, so it can't be saved");
+    }
+    let mut new_file = File::new(file_path.to_string(), TextBuffer::from_lazy_text(LazyText::new(line_index, encoding)));
+    new_file.line_ending = line_ending;
+    new_file.encoding = encoding;
+    set_file_indentation(&mut new_file, &start_text, program_data);
+    Ok(Some(new_file))
+
+}
+
+
+
+// the settings are used when the file isn't indented yet
+pub fn set_file_indentation (file: &mut File, text: &str, program_data: &ProgramData) {
+    let settings = program_data.settings.read();
+    let Some(settings) = settings.as_ref() else {return;};
+    file.tab_width = settings.tab_width;
+    file.indentation = fns::detect_indentation(text).unwrap_or(
+        if settings.insert_spaces {Indentation::Spaces(settings.tab_width)} else {Indentation::Tabs}
+    );
+}
+
+
+
+// indexing is done in chunks so that other tasks don't have to wait for the whole file
+pub fn index_lines (line_index: Arc<LineIndex>, program_data: &ProgramData) {
+    if Arc::strong_count(&line_index) == 1 {return;} // the file was closed or reloaded
+    if !line_index.index_next_chunk() {
+        program_data.tasks.write().push(ProgramTask::IndexLines {line_index});
+    }
+}
+
+
+
+// the file is only changed if the command succeeds for every range and the ranges weren't edited in the meantime
+pub fn filter_text (file_num: usize, file_path: &str, command: &str, ranges: Vec<FilterRange>, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let outputs = ranges.iter()
+        .map(|range| run_filter_command(command, &range.text))
+        .collect::<Result<Vec<String>, ProgramError>>()?;
+
+    let mut files = program_data.files.write();
+    let Some(file) = files.get_mut(file_num).filter(|file| file.path == file_path) else {
+        println!("Warning: {file_path} was closed while running \"{command}\", so the output was discarded");
+        return Ok(());
+    };
+    let is_valid_pos = |(x, y): (usize, usize)| y < file.contents.len_lines() && x <= file.contents.line_len(y);
+    let is_unchanged = ranges.iter().all(|range| is_valid_pos(range.start) && is_valid_pos(range.end) && file.contents.slice(range.start, range.end) == range.text);
+    if !is_unchanged {
+        println!("Warning: {file_path} was changed while running \"{command}\", so the output was discarded");
+        return Ok(());
+    }
+
+    // this goes from the bottom up so that the positions of the other ranges don't change
+    file.history.start_step(&file.cursors);
+    let old_cursors = file.cursors.clone();
+    for (range, output) in ranges.iter().zip(outputs).rev() {
+        file.remove_text(range.start, range.end);
+        let new_end = file.insert_text(range.start.0, range.start.1, &output);
+        if let Some(cursor) = range.cursor_num.and_then(|cursor_num| file.cursors.get_mut(cursor_num)) {
+            cursor.selection_start = Some(range.start);
+            (cursor.x, cursor.y) = new_end;
+            cursor.wanted_x = file.contents.get_column(new_end.0, new_end.1, file.tab_width);
+        }
+    }
+    // when the whole file is replaced, the cursors stay where they were as much as possible
+    if ranges[0].cursor_num.is_none() {
+        file.cursors = old_cursors;
+        for cursor in &mut file.cursors {
+            cursor.y = cursor.y.min(file.contents.len_lines() - 1);
+            cursor.x = cursor.x.min(file.contents.line_len(cursor.y));
+            cursor.wanted_x = file.contents.get_column(cursor.x, cursor.y, file.tab_width);
+            cursor.selection_start = None;
+        }
+    }
+    file.history.finish_step(&file.cursors, EditKind::Other);
+    file.scroll_to_cursor = true;
+
+    Ok(())
+}
+
+// the command is run by the system's shell, so it can use pipes and arguments
+// this runs on the background task thread, so a command that takes too long (or waits for a terminal) is killed instead of blocking every other task
+pub fn run_filter_command (command: &str, input: &str) -> Result<String, ProgramError> {
+    let to_run_error = |error: IoError| ProgramError::from(RawProgramError::CouldNotRunCommand {
+        command: command.to_string(),
+        source: error,
+    });
+    let mut shell_command = if cfg!(windows) {Command::new("cmd")} else {Command::new("sh")};
+    shell_command.arg(if cfg!(windows) {"/C"} else {"-c"}).arg(command);
+    // the command gets its own process group, so that everything it starts can be killed along with it
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        shell_command.process_group(0);
+    }
+    let mut child = shell_command.stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn().map_err(to_run_error)?;
+
+    // the input and output are handled on other threads, otherwise a full pipe could block the command
+    let mut stdin = child.stdin.take().expect("Error: command stdin is none");
+    let input_ends_with_newline = input.ends_with('\n');
+    let input = input.to_string();
+    thread::spawn(move || stdin.write_all(input.as_bytes())); // commands that don't read their input cause a broken pipe, which isn't a problem
+    let read_output = |mut output: Box<dyn Read + Send>| thread::spawn(move || {
+        let mut bytes = vec!();
+        output.read_to_end(&mut bytes).map(|_| bytes)
+    });
+    let stdout_reader = read_output(Box::new(child.stdout.take().expect("Error: command stdout is none")));
+    let stderr_reader = read_output(Box::new(child.stderr.take().expect("Error: command stderr is none")));
+
+    let start_time = Instant::now();
+    let status = loop {
+        if let Some(status) = child.try_wait().map_err(to_run_error)? {break status;}
+        if start_time.elapsed() > FILTER_COMMAND_TIMEOUT {
+            kill_command(&mut child);
+            return err(RawProgramError::CommandTimedOut {
+                command: command.to_string(),
+                timeout: FILTER_COMMAND_TIMEOUT,
+            });
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    let join_reader = |reader: JoinHandle<Result<Vec<u8>, IoError>>| reader.join().expect("Error: command output thread panicked").map_err(to_run_error);
+    let output_stdout = join_reader(stdout_reader)?;
+    let output_stderr = join_reader(stderr_reader)?;
+
+    let stderr = String::from_utf8_lossy(&output_stderr).to_string();
+    if !status.success() || !stderr.trim().is_empty() {
+        return err(RawProgramError::CommandFailed {
+            command: command.to_string(),
+            exit_code: status.code(),
+            stderr,
+        });
+    }
+    let mut stdout = String::from_utf8_lossy(&output_stdout).replace("\r\n", "\n");
+    if !input_ends_with_newline && stdout.ends_with('\n') {stdout.pop();}
+    Ok(stdout)
+}
+
+pub fn kill_command (child: &mut Child) {
+    #[cfg(unix)]
+    {
+        let _ = Command::new("kill").args(["-s", "KILL", "--", &format!("-{}", child.id())]).status();
+    }
+    let _ = child.kill();
+    let _ = child.wait();
+}
+
+
+
+pub fn save_file (file_num: usize, file_path: &str, program_data: &ProgramData) -> Result<(), ProgramError> {
+    println!("Saving file {file_path}");
+
+    let mut files = program_data.files.write();
+    let num_of_files = files.len();
+    let Some(file) = files.get_mut(file_num) else {
+        return err(RawProgramError::InvalidFileIndex {
+            file_index: file_num,
+            num_of_files,
+        });
+    };
+
+    if file.contents.is_lazy() || file.hex_view.as_ref().is_some_and(HexView::is_mapped) {
+        save_mapped_file(file, file_path, program_data)?;
+        file.saved_contents_hash = None;
+    } else {
+        let contents = match &file.hex_view {
+            Some(hex_view) => hex_view.bytes.to_vec(),
+            None => file_encoding::encode(&fns::join_lines(&file.contents, file.line_ending), file.encoding, file_path)?,
+        };
+        if let Err(error) = fs::write(file_path, &contents) {
+            return err(RawProgramError::CouldNotSaveFile {
+                file_path: file_path.to_string(),
+                source: error,
+            });
+        }
+        file.saved_contents_hash = Some(undo_journal::hash_bytes(&contents));
+    }
+
+    file.path = file_path.to_string();
+    file.history.saved_step = file.history.current_step;
+    file.has_mixed_line_endings = false;
+
+    // the journal is written now too so that the history isn't lost if the program doesn't exit normally
+    if let Err(error) = undo_journal::save_journal(file) {
+        println!("Warning: could not save undo history for {file_path}");
+        println!("Error: {:?}", error);
+    }
+
+    println!("saved file {file_path}");
+    Ok(())
+}
+
+
+
+// lazy files and large hex views are still memory-mapped, so they're written to a temporary file that then replaces the original
+pub fn save_mapped_file (file: &mut File, file_path: &str, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let to_save_error = |error: IoError| ProgramError::from(RawProgramError::CouldNotSaveFile {
+        file_path: file_path.to_string(),
+        source: error,
+    });
+    // lines that aren't indexed yet would be left out, so the rest of the file is indexed first
+    if let Some(line_index) = file.contents.get_line_index() {
+        while !line_index.index_next_chunk() {}
+        if *line_index.has_invalid_utf8.read() {
+            return Err(to_save_error(IoError::new(IoErrorKind::InvalidData, "the file has invalid utf-8, which would be replaced if it was saved")));
+        }
+    }
+
+    let (temp_path, temp_file) = create_temp_file(file_path).map_err(to_save_error)?;
+    let mut writer = BufWriter::new(temp_file);
+
+    let write_result = match &file.hex_view {
+        Some(hex_view) => writer.write_all(&hex_view.bytes).map_err(to_save_error),
+        None => {
+            // encoding an empty string only gives the byte order mark (if there is one)
+            let byte_order_mark = file_encoding::encode("", file.encoding, file_path)?;
+            writer.write_all(&byte_order_mark).map_err(to_save_error).and_then(|_| {
+                file.contents.write_lines(|line, is_last_line| {
+                    let line_ending = if is_last_line {""} else {file.line_ending.as_str()};
+                    let line_bytes = file_encoding::encode(&format!("{line}{line_ending}"), file.encoding, file_path)?;
+                    writer.write_all(&line_bytes[byte_order_mark.len()..]).map_err(to_save_error)
+                })
+            })
+        }
+    }.and_then(|_| writer.flush().map_err(to_save_error));
+    drop(writer);
+    if let Err(error) = write_result {
+        let _ = fs::remove_file(&temp_path);
+        return Err(error);
+    }
+
+    // the new file gets the same permissions (and owner, when that's allowed) as the one it replaces
+    if let Ok(metadata) = fs::metadata(file_path) {
+        fs::set_permissions(&temp_path, metadata.permissions()).map_err(to_save_error)?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::MetadataExt;
+            let _ = std::os::unix::fs::chown(&temp_path, Some(metadata.uid()), Some(metadata.gid()));
+        }
+    }
+
+    // windows can't replace a file that's memory-mapped, so the old map is dropped first and the saved file is mapped instead
+    let mut hex_view_position = None;
+    if cfg!(windows) {
+        hex_view_position = file.hex_view.take().map(|hex_view| (hex_view.cursor, hex_view.scroll_row));
+        file.contents = TextBuffer::new();
+        program_data.tasks.write().retain(|task| !matches!(task, ProgramTask::IndexLines {line_index} if Arc::strong_count(line_index) == 1));
+    }
+    fs::rename(&temp_path, file_path).map_err(to_save_error)?;
+    if cfg!(windows) {
+        let new_file = match read_file_lazily(file_path, program_data)? {
+            Some(new_file) => new_file,
+            None => read_file(file_path, Some(file.encoding), program_data)?,
+        };
+        file.contents = new_file.contents;
+        file.hex_view = new_file.hex_view;
+        if let (Some(hex_view), Some((cursor, scroll_row))) = (&mut file.hex_view, hex_view_position) {
+            (hex_view.cursor, hex_view.scroll_row) = (cursor, scroll_row);
+        }
+    }
+    Ok(())
+}
+
+// existing files are never overwritten, in case one happens to have the same name
+pub fn create_temp_file (file_path: &str) -> Result<(String, fs::File), IoError> {
+    let mut temp_num = 0;
+    loop {
+        let temp_path = if temp_num == 0 {format!("{file_path}.tmp")} else {format!("{file_path}.{temp_num}.tmp")};
+        match fs::OpenOptions::new().write(true).create_new(true).open(&temp_path) {
+            Ok(temp_file) => return Ok((temp_path, temp_file)),
+            Err(error) if error.kind() == IoErrorKind::AlreadyExists => temp_num += 1,
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filter_commands_get_the_input () {
+        assert_eq!(run_filter_command("cat", "b\na").unwrap(), "b\na");
+        assert_eq!(run_filter_command("sort", "b\na\n").unwrap(), "a\nb\n");
+        // the output is read while the input is still being written
+        let large_input = "line\n".repeat(1024 * 1024);
+        assert_eq!(run_filter_command("cat", &large_input).unwrap(), large_input);
+    }
+
+    #[test]
+    fn failed_filter_commands_are_errors () {
+        assert!(matches!(run_filter_command("exit 1", "").unwrap_err().raw, RawProgramError::CommandFailed {exit_code: Some(1), ..}));
+        assert!(matches!(run_filter_command("echo error >&2", "").unwrap_err().raw, RawProgramError::CommandFailed {..}));
+    }
+
+}
@@ -0,0 +1,149 @@
+use crate::prelude::*;
+
+
+
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+const UTF16_LE_BOM: [u8; 2] = [0xFF, 0xFE];
+const UTF16_BE_BOM: [u8; 2] = [0xFE, 0xFF];
+
+
+
+pub fn detect_encoding (bytes: &[u8]) -> TextEncoding {
+    if bytes.starts_with(&UTF8_BOM) {return TextEncoding::Utf8Bom;}
+    if bytes.starts_with(&UTF16_LE_BOM) {return TextEncoding::Utf16LeBom;}
+    if bytes.starts_with(&UTF16_BE_BOM) {return TextEncoding::Utf16BeBom;}
+    if let Some(encoding) = detect_bomless_utf16(bytes) {return encoding;} // this has to come first since ascii utf-16 is also valid utf-8
+    if std::str::from_utf8(bytes).is_ok() {return TextEncoding::Utf8;}
+    TextEncoding::Latin1
+}
+
+
+
+// mostly-ascii utf-16 text has a zero byte in every other position
+pub fn detect_bomless_utf16 (bytes: &[u8]) -> Option<TextEncoding> {
+    if bytes.len() < 2 || !bytes.len().is_multiple_of(2) {return None;}
+    let num_of_pairs = bytes.len() / 2;
+    let even_zeros = bytes.iter().step_by(2).filter(|byte| **byte == 0).count();
+    let odd_zeros = bytes.iter().skip(1).step_by(2).filter(|byte| **byte == 0).count();
+    if odd_zeros * 10 > num_of_pairs * 4 && even_zeros * 10 < num_of_pairs {return Some(TextEncoding::Utf16Le);}
+    if even_zeros * 10 > num_of_pairs * 4 && odd_zeros * 10 < num_of_pairs {return Some(TextEncoding::Utf16Be);}
+    None
+}
+
+
+
+// binary files are detected by looking for null bytes or lots of control characters near the start
+pub fn is_binary (bytes: &[u8]) -> bool {
+    if bytes.starts_with(&UTF8_BOM) || bytes.starts_with(&UTF16_LE_BOM) || bytes.starts_with(&UTF16_BE_BOM) {return false;}
+    if detect_bomless_utf16(bytes).is_some() {return false;}
+    let start = &bytes[..bytes.len().min(8192)];
+    if start.contains(&0) {return true;}
+    let control_count = start.iter().filter(|byte| byte.is_ascii_control() && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)).count();
+    control_count * 10 > start.len()
+}
+
+
+
+// invalid sequences are replaced instead of failing, so any file can be opened with any encoding
+pub fn decode (bytes: &[u8], encoding: TextEncoding) -> String {
+    match encoding {
+        TextEncoding::Utf8 => String::from_utf8_lossy(bytes).to_string(),
+        TextEncoding::Utf8Bom => String::from_utf8_lossy(bytes.strip_prefix(&UTF8_BOM).unwrap_or(bytes)).to_string(),
+        TextEncoding::Utf16Le => decode_utf16(bytes, u16::from_le_bytes),
+        TextEncoding::Utf16LeBom => decode_utf16(bytes.strip_prefix(&UTF16_LE_BOM).unwrap_or(bytes), u16::from_le_bytes),
+        TextEncoding::Utf16Be => decode_utf16(bytes, u16::from_be_bytes),
+        TextEncoding::Utf16BeBom => decode_utf16(bytes.strip_prefix(&UTF16_BE_BOM).unwrap_or(bytes), u16::from_be_bytes),
+        TextEncoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+    }
+}
+
+pub fn decode_utf16 (bytes: &[u8], to_u16_fn: fn([u8; 2]) -> u16) -> String {
+    let units = bytes.chunks_exact(2).map(|pair| to_u16_fn([pair[0], pair[1]]));
+    char::decode_utf16(units)
+        .map(|char| char.unwrap_or(char::REPLACEMENT_CHARACTER))
+        .collect()
+}
+
+
+
+pub fn encode (text: &str, encoding: TextEncoding, file_path: &str) -> Result<Vec<u8>, ProgramError> {
+    Ok(match encoding {
+        TextEncoding::Utf8 => text.as_bytes().to_vec(),
+        TextEncoding::Utf8Bom => chain!(UTF8_BOM, text.bytes()).collect(),
+        TextEncoding::Utf16Le => text.encode_utf16().flat_map(u16::to_le_bytes).collect(),
+        TextEncoding::Utf16LeBom => chain!(UTF16_LE_BOM, text.encode_utf16().flat_map(u16::to_le_bytes)).collect(),
+        TextEncoding::Utf16Be => text.encode_utf16().flat_map(u16::to_be_bytes).collect(),
+        TextEncoding::Utf16BeBom => chain!(UTF16_BE_BOM, text.encode_utf16().flat_map(u16::to_be_bytes)).collect(),
+        TextEncoding::Latin1 => {
+            let mut output = Vec::with_capacity(text.len());
+            for char in text.chars() {
+                if char as u32 > 0xFF {
+                    return err(RawProgramError::CouldNotEncodeText {
+                        file_path: file_path.to_string(),
+                        encoding,
+                        character: char,
+                    });
+                }
+                output.push(char as u8);
+            }
+            output
+        }
+    })
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ENCODINGS: [TextEncoding; 7] = [
+        TextEncoding::Utf8,
+        TextEncoding::Utf8Bom,
+        TextEncoding::Utf16Le,
+        TextEncoding::Utf16LeBom,
+        TextEncoding::Utf16Be,
+        TextEncoding::Utf16BeBom,
+        TextEncoding::Latin1,
+    ];
+
+    #[test]
+    fn encode_and_decode_round_trip () {
+        for encoding in ENCODINGS {
+            let text = "line one\nline two: caf\u{e9}";
+            let bytes = encode(text, encoding, "test").unwrap();
+            assert_eq!(decode(&bytes, encoding), text, "{encoding:?}");
+        }
+    }
+
+    #[test]
+    fn detected_encoding_round_trips () {
+        for encoding in ENCODINGS {
+            let bytes = encode("some text\nmore text caf\u{e9}\n", encoding, "test").unwrap();
+            assert!(!is_binary(&bytes), "{encoding:?}");
+            let detected_encoding = detect_encoding(&bytes);
+            assert_eq!(detected_encoding, encoding);
+            assert_eq!(encode(&decode(&bytes, detected_encoding), detected_encoding, "test").unwrap(), bytes);
+        }
+    }
+
+    #[test]
+    fn only_bom_encodings_write_a_bom () {
+        assert_eq!(encode("", TextEncoding::Utf16Le, "test").unwrap(), Vec::<u8>::new());
+        assert_eq!(encode("", TextEncoding::Utf16BeBom, "test").unwrap(), UTF16_BE_BOM);
+    }
+
+    #[test]
+    fn latin1_cant_encode_wide_chars () {
+        assert!(encode("\u{4e2d}", TextEncoding::Latin1, "test").is_err());
+    }
+
+    #[test]
+    fn binary_detection () {
+        assert!(is_binary(&[0x7F, 0x45, 0x4C, 0x46, 0x02, 0x01, 0x01, 0x00]));
+        assert!(!is_binary(b"plain text\n"));
+        assert!(!is_binary(b""));
+    }
+
+}
@@ -1 +1,3 @@
 pub mod background_tasks;
+pub mod undo_journal;
+pub mod file_encoding;
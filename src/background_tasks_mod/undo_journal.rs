@@ -0,0 +1,272 @@
+use crate::prelude::*;
+use std::{path::PathBuf, time::{SystemTime, UNIX_EPOCH}};
+
+
+
+// Undo histories are saved to `undo_history/<path hash>.hjson` in the program dir, along with
+// the hash of the file's contents at the time that it was last loaded or saved. If the file's
+// contents don't match that hash when it's loaded again, the history is thrown away. Journals
+// are written whenever a file is saved and again when the program exits.
+
+
+
+pub fn get_journal_path (file_path: &str) -> PathBuf {
+    let full_file_path = fs::canonicalize(file_path).unwrap_or_else(|_| PathBuf::from(file_path));
+    let mut journal_path = fns::get_program_dir();
+    journal_path.push("undo_history");
+    journal_path.push(format!("{:016x}.hjson", hash_bytes(full_file_path.to_string_lossy().as_bytes())));
+    journal_path
+}
+
+
+
+// this is 64-bit FNV-1a, the hashes are saved to disk so they can't change between rust versions like the std hasher can
+pub fn hash_bytes (bytes: &[u8]) -> u64 {
+    let mut hash = 0xcbf29ce484222325_u64;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+
+
+
+
+pub fn save_journal (file: &File) -> Result<(), ProgramError> {
+    let journal_path = get_journal_path(&file.path);
+
+    let Some(saved_contents_hash) = file.saved_contents_hash else {
+        return delete_journal(&journal_path);
+    };
+    if file.history.steps.len() == 1 {
+        return delete_journal(&journal_path);
+    }
+
+    // don't write a journal that no longer matches the file on disk
+    let disk_contents = match fs::read(&file.path) {
+        Ok(v) => v,
+        Err(error) => return err(RawProgramError::CouldNotLoadFile {
+            file_path: file.path.to_string(),
+            source: error,
+        }),
+    };
+    if hash_bytes(&disk_contents) != saved_contents_hash {
+        println!("Warning: file {} was changed outside of the editor, its undo history will not be saved", file.path);
+        return delete_journal(&journal_path);
+    }
+
+    let mut journal = Map::new();
+    journal.insert(String::from("file path"), Value::String(file.path.to_string()));
+    journal.insert(String::from("contents hash"), Value::U64(saved_contents_hash));
+    journal.insert(String::from("saved step"), Value::U64(file.history.saved_step as u64));
+    journal.insert(String::from("steps"), Value::Array(file.history.steps.iter().map(step_to_hjson).collect()));
+    let journal_string = serde_hjson::to_string(&Value::Object(journal))?;
+
+    let write_result = fs::create_dir_all(journal_path.parent().unwrap())
+        .and_then(|_| fs::write(&journal_path, journal_string));
+    if let Err(error) = write_result {
+        return err(RawProgramError::CouldNotSaveFile {
+            file_path: journal_path.to_string_lossy().to_string(),
+            source: error,
+        });
+    }
+
+    Ok(())
+}
+
+
+
+pub fn delete_journal (journal_path: &PathBuf) -> Result<(), ProgramError> {
+    match fs::remove_file(journal_path) {
+        Ok(_) => Ok(()),
+        Err(error) if error.kind() == IoErrorKind::NotFound => Ok(()),
+        Err(error) => err(RawProgramError::CouldNotSaveFile {
+            file_path: journal_path.to_string_lossy().to_string(),
+            source: error,
+        }),
+    }
+}
+
+
+
+// returns None if there is no journal or if the journal doesn't match the file's contents
+pub fn load_journal (file_path: &str, contents_hash: u64) -> Result<Option<EditHistory>, ProgramError> {
+    let journal_path = get_journal_path(file_path);
+
+    let raw_journal = match fs::read_to_string(&journal_path) {
+        Ok(v) => v,
+        Err(error) if error.kind() == IoErrorKind::NotFound => return Ok(None),
+        Err(error) => return err(RawProgramError::CouldNotLoadFile {
+            file_path: journal_path.to_string_lossy().to_string(),
+            source: error,
+        }),
+    };
+
+    let history = serde_hjson::from_str::<Value>(&raw_journal)
+        .map_err(ProgramError::from)
+        .and_then(|journal| history_from_hjson(&journal, contents_hash));
+    match history {
+        Ok(Some(history)) => Ok(Some(history)),
+        Ok(None) => {
+            println!("Warning: file {file_path} was changed outside of the editor, its undo history will be discarded");
+            delete_journal(&journal_path)?;
+            Ok(None)
+        }
+        Err(error) => {
+            delete_journal(&journal_path)?;
+            Err(error)
+        }
+    }
+}
+
+
+
+
+
+fn history_from_hjson (journal: &Value, contents_hash: u64) -> Result<Option<EditHistory>, ProgramError> {
+
+    let journal_contents_hash = get_journal_value(journal, "contents hash", Value::as_u64)?;
+    if journal_contents_hash != contents_hash {return Ok(None);}
+
+    let saved_step = get_journal_value(journal, "saved step", Value::as_u64)? as usize;
+    let mut steps = get_journal_value(journal, "steps", Value::as_array)?.iter()
+        .map(step_from_hjson)
+        .collect::<Result<Vec<EditStep>, ProgramError>>()?;
+    if steps.is_empty() || saved_step >= steps.len() {
+        return err(RawProgramError::InvalidUndoJournal {details: String::from("saved step is out of range")});
+    }
+
+    // parents always come before their children, which also means the tree can't have loops
+    for i in 1..steps.len() {
+        let parent = steps[i].parent;
+        if parent >= i {
+            return err(RawProgramError::InvalidUndoJournal {details: format!("step {i} has an invalid parent")});
+        }
+        steps[parent].children.push(i);
+    }
+    for step in &mut steps {
+        if step.redo_child.is_some_and(|redo_child| !step.children.contains(&redo_child)) {
+            step.redo_child = None;
+        }
+    }
+
+    // the file on disk is the saved state
+    Ok(Some(EditHistory {
+        steps,
+        current_step: saved_step,
+        pending_step: None,
+        saved_step,
+    }))
+
+}
+
+
+
+fn step_to_hjson (step: &EditStep) -> Value {
+    let mut output = Map::new();
+    output.insert(String::from("kind"), Value::String(String::from(match step.kind {
+        EditKind::Typing => "typing",
+        EditKind::Other => "other",
+    })));
+    output.insert(String::from("cursors before"), Value::Array(step.cursors_before.iter().map(cursor_to_hjson).collect()));
+    output.insert(String::from("cursors after"), Value::Array(step.cursors_after.iter().map(cursor_to_hjson).collect()));
+    output.insert(String::from("edits"), Value::Array(step.edits.iter().map(edit_to_hjson).collect()));
+    output.insert(String::from("time"), Value::U64(step.time.duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs())));
+    output.insert(String::from("parent"), Value::U64(step.parent as u64));
+    output.insert(String::from("redo child"), step.redo_child.map_or(Value::Null, |redo_child| Value::U64(redo_child as u64)));
+    Value::Object(output)
+}
+
+fn step_from_hjson (step: &Value) -> Result<EditStep, ProgramError> {
+    let kind = match get_journal_value(step, "kind", Value::as_str)? {
+        "typing" => EditKind::Typing,
+        "other" => EditKind::Other,
+        _ => return err(RawProgramError::InvalidUndoJournal {details: String::from("unknown step kind")}),
+    };
+    Ok(EditStep {
+        edits: get_journal_value(step, "edits", Value::as_array)?.iter().map(edit_from_hjson).collect::<Result<_, _>>()?,
+        cursors_before: get_journal_value(step, "cursors before", Value::as_array)?.iter().map(cursor_from_hjson).collect::<Result<_, _>>()?,
+        cursors_after: get_journal_value(step, "cursors after", Value::as_array)?.iter().map(cursor_from_hjson).collect::<Result<_, _>>()?,
+        kind,
+        time: UNIX_EPOCH + Duration::from_secs(get_journal_value(step, "time", Value::as_u64)?),
+        parent: get_journal_value(step, "parent", Value::as_u64)? as usize,
+        children: vec!(),
+        redo_child: step.find("redo child").and_then(Value::as_u64).map(|redo_child| redo_child as usize),
+    })
+}
+
+
+
+fn edit_to_hjson (edit: &Edit) -> Value {
+    let mut output = Map::new();
+    output.insert(String::from("x"), Value::U64(edit.start.0 as u64));
+    output.insert(String::from("y"), Value::U64(edit.start.1 as u64));
+    output.insert(String::from("removed"), Value::String(edit.removed_text.to_string()));
+    output.insert(String::from("inserted"), Value::String(edit.inserted_text.to_string()));
+    Value::Object(output)
+}
+
+fn edit_from_hjson (edit: &Value) -> Result<Edit, ProgramError> {
+    Ok(Edit {
+        start: (
+            get_journal_value(edit, "x", Value::as_u64)? as usize,
+            get_journal_value(edit, "y", Value::as_u64)? as usize,
+        ),
+        removed_text: get_journal_value(edit, "removed", Value::as_str)?.to_string(),
+        inserted_text: get_journal_value(edit, "inserted", Value::as_str)?.to_string(),
+    })
+}
+
+
+
+// cursors are stored as [x, y, wanted x] or [x, y, wanted x, selection x, selection y]
+fn cursor_to_hjson (cursor: &Cursor) -> Value {
+    let mut output = vec!(
+        Value::U64(cursor.x as u64),
+        Value::U64(cursor.y as u64),
+        Value::U64(cursor.wanted_x as u64),
+    );
+    if let Some((selection_x, selection_y)) = cursor.selection_start {
+        output.push(Value::U64(selection_x as u64));
+        output.push(Value::U64(selection_y as u64));
+    }
+    Value::Array(output)
+}
+
+fn cursor_from_hjson (cursor: &Value) -> Result<Cursor, ProgramError> {
+    let values = cursor.as_array()
+        .map(|values| values.iter().filter_map(Value::as_u64).map(|v| v as usize).collect::<Vec<usize>>())
+        .unwrap_or_default();
+    match values[..] {
+        [x, y, wanted_x] => Ok(Cursor {x, y, wanted_x, selection_start: None}),
+        [x, y, wanted_x, selection_x, selection_y] => Ok(Cursor {x, y, wanted_x, selection_start: Some((selection_x, selection_y))}),
+        _ => err(RawProgramError::InvalidUndoJournal {details: String::from("invalid cursor")}),
+    }
+}
+
+
+
+fn get_journal_value<'a, T> (value: &'a Value, key: &str, value_fn: impl FnOnce(&'a Value) -> Option<T>) -> Result<T, ProgramError> {
+    match value.find(key).and_then(value_fn) {
+        Some(v) => Ok(v),
+        None => err(RawProgramError::InvalidUndoJournal {details: format!("missing or invalid value \"{key}\"")}),
+    }
+}
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hash_bytes_is_fnv_1a () {
+        assert_eq!(hash_bytes(b""), 0xcbf29ce484222325);
+        assert_eq!(hash_bytes(b"a"), 0xaf63dc4c8601ec8c);
+        assert_eq!(hash_bytes(b"foobar"), 0x85944171f73967e8);
+    }
+
+}
@@ -0,0 +1,367 @@
+use crate::prelude::*;
+use std::time::SystemTime;
+
+
+
+// consecutive typing steps are merged if they are closer together than this
+pub const TYPING_GROUP_TIME: Duration = Duration::from_secs(2);
+
+
+
+// The history is a tree of steps, where step 0 is the file's original state and every other
+// step is a change from its parent. Steps are stored in the order they were made, so moving
+// through the step indices moves through the file's states in time.
+#[derive(Debug)]
+pub struct EditHistory {
+    pub steps: Vec<EditStep>,
+    pub current_step: usize,
+    pub pending_step: Option<PendingEditStep>,
+    pub saved_step: usize, // the step that matches the file on disk
+}
+
+impl Default for EditHistory {
+    fn default() -> Self {
+        Self {
+            steps: vec!(EditStep::new_root()),
+            current_step: 0,
+            pending_step: None,
+            saved_step: 0,
+        }
+    }
+}
+
+impl EditHistory {
+
+
+
+    pub fn start_step (&mut self, cursors: &[Cursor]) {
+        self.pending_step = Some(PendingEditStep {
+            edits: vec!(),
+            cursors_before: cursors.to_vec(),
+        });
+    }
+
+    pub fn record_edit (&mut self, edit: Edit) {
+        if let Some(pending_step) = &mut self.pending_step {
+            pending_step.edits.push(edit);
+        }
+    }
+
+    pub fn finish_step (&mut self, cursors: &[Cursor], kind: EditKind) {
+        let Some(pending_step) = self.pending_step.take() else {return;};
+        if pending_step.edits.is_empty() {return;}
+
+        let new_step = EditStep {
+            edits: pending_step.edits,
+            cursors_before: pending_step.cursors_before,
+            cursors_after: cursors.to_vec(),
+            kind,
+            time: SystemTime::now(),
+            parent: self.current_step,
+            children: vec!(),
+            redo_child: None,
+        };
+
+        // only the newest step can be merged into, otherwise the steps would no longer be in time order
+        let can_merge = self.saved_step != self.current_step && self.current_step == self.steps.len() - 1;
+        let new_step_index = self.steps.len();
+        let last_step = &mut self.steps[self.current_step];
+        if can_merge && last_step.can_merge_with(&new_step) {
+            last_step.edits.extend(new_step.edits);
+            last_step.cursors_after = new_step.cursors_after;
+            last_step.time = new_step.time;
+            return;
+        }
+
+        last_step.children.push(new_step_index);
+        last_step.redo_child = Some(new_step_index);
+        self.steps.push(new_step);
+        self.current_step = new_step_index;
+
+    }
+
+
+
+    // these return the cursors to use after moving, or None if nothing changed
+
+    pub fn undo (&mut self, contents: &mut TextBuffer) -> Option<Vec<Cursor>> {
+        if self.current_step == 0 {return None;}
+        let step = &self.steps[self.current_step];
+        step.revert(contents);
+        let (cursors, parent) = (step.cursors_before.clone(), step.parent);
+        self.steps[parent].redo_child = Some(self.current_step);
+        self.current_step = parent;
+        Some(cursors)
+    }
+
+    pub fn redo (&mut self, contents: &mut TextBuffer) -> Option<Vec<Cursor>> {
+        let current_step = &self.steps[self.current_step];
+        let next_step = current_step.redo_child.or_else(|| current_step.children.last().copied())?;
+        self.steps[next_step].apply(contents);
+        self.current_step = next_step;
+        Some(self.steps[next_step].cursors_after.clone())
+    }
+
+    pub fn go_to_step (&mut self, target_step: usize, contents: &mut TextBuffer) -> Option<Vec<Cursor>> {
+        if target_step >= self.steps.len() || target_step == self.current_step {return None;}
+
+        // undo up to the common ancestor, then redo down to the target
+        let target_path = self.get_path_to(target_step);
+        let mut cursors = vec!();
+        while !target_path.contains(&self.current_step) {
+            cursors = self.undo(contents).unwrap();
+        }
+        let common_step_index = target_path.iter().position(|step| *step == self.current_step).unwrap();
+        for next_step in target_path.iter().skip(common_step_index + 1) {
+            self.steps[self.current_step].redo_child = Some(*next_step);
+            cursors = self.redo(contents).unwrap();
+        }
+
+        Some(cursors)
+    }
+
+    pub fn go_to_earlier_step (&mut self, contents: &mut TextBuffer) -> Option<Vec<Cursor>> {
+        if self.current_step == 0 {return None;}
+        self.go_to_step(self.current_step - 1, contents)
+    }
+
+    pub fn go_to_later_step (&mut self, contents: &mut TextBuffer) -> Option<Vec<Cursor>> {
+        self.go_to_step(self.current_step + 1, contents)
+    }
+
+    // changes which branch redo will follow from the current step
+    pub fn switch_redo_branch (&mut self) -> bool {
+        let current_step = &mut self.steps[self.current_step];
+        if current_step.children.len() < 2 {return false;}
+        let redo_child_index = current_step.redo_child
+            .and_then(|redo_child| current_step.children.iter().position(|child| *child == redo_child))
+            .unwrap_or(current_step.children.len() - 1);
+        current_step.redo_child = Some(current_step.children[(redo_child_index + 1) % current_step.children.len()]);
+        true
+    }
+
+
+
+    // returns the steps from the root to the given step (inclusive)
+    pub fn get_path_to (&self, mut step: usize) -> Vec<usize> {
+        let mut output = vec!(step);
+        while step != 0 {
+            step = self.steps[step].parent;
+            output.push(step);
+        }
+        output.reverse();
+        output
+    }
+
+    // returns the last step that redo would eventually reach from the current step
+    pub fn get_redo_tip (&self) -> usize {
+        let mut step = self.current_step;
+        loop {
+            let current_step = &self.steps[step];
+            match current_step.redo_child.or_else(|| current_step.children.last().copied()) {
+                Some(next_step) => step = next_step,
+                None => return step,
+            }
+        }
+    }
+
+    pub fn get_branches (&self) -> Vec<HistoryBranch> {
+        let redo_tip = self.get_redo_tip();
+        self.steps.iter().enumerate()
+            .filter(|(_, step)| step.children.is_empty())
+            .map(|(tip_step, step)| {
+                let path = self.get_path_to(tip_step);
+                HistoryBranch {
+                    tip_step,
+                    len: path.len() - 1,
+                    time: step.time,
+                    contains_current_step: path.contains(&self.current_step),
+                    is_redo_branch: tip_step == redo_tip,
+                }
+            })
+            .collect()
+    }
+
+
+
+}
+
+
+
+#[derive(Debug)]
+pub struct PendingEditStep {
+    pub edits: Vec<Edit>,
+    pub cursors_before: Vec<Cursor>,
+}
+
+
+
+#[derive(Debug)]
+pub struct EditStep {
+    pub edits: Vec<Edit>,
+    pub cursors_before: Vec<Cursor>,
+    pub cursors_after: Vec<Cursor>,
+    pub kind: EditKind,
+    pub time: SystemTime,
+    pub parent: usize,
+    pub children: Vec<usize>,
+    pub redo_child: Option<usize>,
+}
+
+impl EditStep {
+
+    pub fn new_root () -> Self {
+        Self {
+            edits: vec!(),
+            cursors_before: vec!(),
+            cursors_after: vec!(),
+            kind: EditKind::Other,
+            time: SystemTime::now(),
+            parent: 0,
+            children: vec!(),
+            redo_child: None,
+        }
+    }
+
+    pub fn can_merge_with (&self, next_step: &EditStep) -> bool {
+        self.kind == EditKind::Typing
+            && next_step.kind == EditKind::Typing
+            && self.cursors_after == next_step.cursors_before
+            && next_step.time.duration_since(self.time).is_ok_and(|time| time < TYPING_GROUP_TIME)
+    }
+
+    pub fn apply (&self, contents: &mut TextBuffer) {
+        for edit in &self.edits {
+            let removed_end = fns::get_text_end_pos(edit.start, &edit.removed_text);
+            contents.remove(edit.start, removed_end);
+            contents.insert(edit.start.0, edit.start.1, &edit.inserted_text);
+        }
+    }
+
+    pub fn revert (&self, contents: &mut TextBuffer) {
+        for edit in self.edits.iter().rev() {
+            let inserted_end = fns::get_text_end_pos(edit.start, &edit.inserted_text);
+            contents.remove(edit.start, inserted_end);
+            contents.insert(edit.start.0, edit.start.1, &edit.removed_text);
+        }
+    }
+
+}
+
+
+
+#[derive(Debug)]
+pub struct HistoryBranch {
+    pub tip_step: usize,
+    pub len: usize,
+    pub time: SystemTime,
+    pub contains_current_step: bool,
+    pub is_redo_branch: bool,
+}
+
+
+
+#[derive(Debug, Clone)]
+pub struct Edit {
+    pub start: (usize, usize),
+    pub removed_text: String,
+    pub inserted_text: String,
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Typing,
+    Other,
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_step (history: &mut EditHistory, contents: &mut TextBuffer, x: usize, text: &str) {
+        insert_step_of_kind(history, contents, x, text, EditKind::Other);
+    }
+
+    fn insert_step_of_kind (history: &mut EditHistory, contents: &mut TextBuffer, x: usize, text: &str, kind: EditKind) {
+        history.start_step(&[]);
+        contents.insert(x, 0, text);
+        history.record_edit(Edit {start: (x, 0), removed_text: String::new(), inserted_text: text.to_string()});
+        history.finish_step(&[], kind);
+    }
+
+    // builds "ab" and then undoes to "a" and branches off to "ac"
+    fn get_branched_history () -> (EditHistory, TextBuffer) {
+        let mut history = EditHistory::default();
+        let mut contents = TextBuffer::new();
+        insert_step(&mut history, &mut contents, 0, "a");
+        insert_step(&mut history, &mut contents, 1, "b");
+        history.undo(&mut contents);
+        insert_step(&mut history, &mut contents, 1, "c");
+        (history, contents)
+    }
+
+    #[test]
+    fn undo_and_redo () {
+        let (mut history, mut contents) = get_branched_history();
+        assert_eq!(contents.to_string(), "ac");
+        assert!(history.undo(&mut contents).is_some());
+        assert!(history.undo(&mut contents).is_some());
+        assert!(history.undo(&mut contents).is_none());
+        assert_eq!(contents.to_string(), "");
+        history.redo(&mut contents);
+        history.redo(&mut contents);
+        assert_eq!(contents.to_string(), "ac");
+    }
+
+    #[test]
+    fn go_to_earlier_step_goes_through_every_state () {
+        let (mut history, mut contents) = get_branched_history();
+        history.go_to_earlier_step(&mut contents);
+        assert_eq!(contents.to_string(), "ab");
+        history.go_to_earlier_step(&mut contents);
+        assert_eq!(contents.to_string(), "a");
+        history.go_to_earlier_step(&mut contents);
+        assert_eq!(contents.to_string(), "");
+        assert!(history.go_to_earlier_step(&mut contents).is_none());
+        history.go_to_later_step(&mut contents);
+        history.go_to_later_step(&mut contents);
+        history.go_to_later_step(&mut contents);
+        assert_eq!(contents.to_string(), "ac");
+    }
+
+    #[test]
+    fn switch_redo_branch () {
+        let (mut history, mut contents) = get_branched_history();
+        history.undo(&mut contents);
+        assert!(history.switch_redo_branch());
+        history.redo(&mut contents);
+        assert_eq!(contents.to_string(), "ab");
+        history.undo(&mut contents);
+        assert!(history.switch_redo_branch());
+        history.redo(&mut contents);
+        assert_eq!(contents.to_string(), "ac");
+        assert!(!history.switch_redo_branch());
+    }
+
+    #[test]
+    fn typing_only_merges_into_the_newest_step () {
+        let mut history = EditHistory::default();
+        let mut contents = TextBuffer::new();
+        insert_step_of_kind(&mut history, &mut contents, 0, "a", EditKind::Typing);
+        insert_step_of_kind(&mut history, &mut contents, 1, "b", EditKind::Typing);
+        assert_eq!(history.steps.len(), 2);
+        history.undo(&mut contents);
+        insert_step_of_kind(&mut history, &mut contents, 0, "c", EditKind::Typing);
+        history.go_to_step(1, &mut contents);
+        insert_step_of_kind(&mut history, &mut contents, 2, "d", EditKind::Typing);
+        assert_eq!(history.steps.len(), 4);
+        assert_eq!(contents.to_string(), "abd");
+        history.go_to_earlier_step(&mut contents);
+        assert_eq!(contents.to_string(), "c");
+    }
+
+}
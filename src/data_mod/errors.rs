@@ -76,6 +76,37 @@ pub enum RawProgramError {
         source: IoError,
     },
 
+    CouldNotSaveFile {
+        file_path: String,
+        source: IoError,
+    },
+
+    CouldNotEncodeText {
+        file_path: String,
+        encoding: TextEncoding,
+        character: char,
+    },
+
+    InvalidUndoJournal {
+        details: String,
+    },
+
+    CouldNotRunCommand {
+        command: String,
+        source: IoError,
+    },
+
+    CommandFailed {
+        command: String,
+        exit_code: Option<i32>,
+        stderr: String,
+    },
+
+    CommandTimedOut {
+        command: String,
+        timeout: Duration,
+    },
+
     SerdeError (SerdeError),
     TextureValueError (TextureValueError),
     String (String),
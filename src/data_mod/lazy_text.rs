@@ -0,0 +1,328 @@
+use crate::prelude::*;
+use memmap2::Mmap;
+
+
+
+// Large files are memory-mapped instead of being read into memory. The line index is built in
+// chunks by a background task, and lines are only decoded when they're needed. Edited lines are
+// kept in memory, while unedited lines keep pointing into the mapped file.
+
+
+
+pub const LINE_INDEX_CHUNK_SIZE: usize = 16 * 1024 * 1024;
+
+
+
+#[derive(Debug)]
+pub struct LineIndex {
+    pub mmap: Mmap,
+    pub line_starts: RwLock<Vec<usize>>,
+    pub indexed_bytes: RwLock<usize>,
+    pub checks_utf8: bool,
+    pub utf8_checked_bytes: RwLock<usize>,
+    pub has_invalid_utf8: RwLock<bool>, // invalid bytes are shown as U+FFFD, so saving would replace them
+}
+
+impl LineIndex {
+
+
+
+    pub fn new (mmap: Mmap, checks_utf8: bool) -> Self {
+        Self {
+            mmap,
+            line_starts: RwLock::new(vec!(0)),
+            indexed_bytes: RwLock::new(0),
+            checks_utf8,
+            utf8_checked_bytes: RwLock::new(0),
+            has_invalid_utf8: RwLock::new(false),
+        }
+    }
+
+    // returns true once the whole file has been indexed
+    pub fn index_next_chunk (&self) -> bool {
+        let start = *self.indexed_bytes.read();
+        let end = (start + LINE_INDEX_CHUNK_SIZE).min(self.mmap.len());
+        let new_line_starts = self.mmap[start..end].iter().enumerate()
+            .filter(|(_, byte)| **byte == b'\n')
+            .map(|(i, _)| start + i + 1)
+            .collect::<Vec<usize>>();
+        self.line_starts.write().extend(new_line_starts);
+        if self.checks_utf8 {self.check_utf8(end);}
+        *self.indexed_bytes.write() = end;
+        end == self.mmap.len()
+    }
+
+    fn check_utf8 (&self, end: usize) {
+        if *self.has_invalid_utf8.read() {return;}
+        let start = *self.utf8_checked_bytes.read();
+        match std::str::from_utf8(&self.mmap[start..end]) {
+            Ok(_) => *self.utf8_checked_bytes.write() = end,
+            // a char can be cut off by the end of the chunk, in which case it's checked along with the next chunk
+            Err(error) if error.error_len().is_none() && end < self.mmap.len() => *self.utf8_checked_bytes.write() = start + error.valid_up_to(),
+            Err(_) => *self.has_invalid_utf8.write() = true,
+        }
+    }
+
+    pub fn is_done (&self) -> bool {
+        *self.indexed_bytes.read() == self.mmap.len()
+    }
+
+    pub fn get_progress (&self) -> f64 {
+        if self.mmap.is_empty() {return 1.;}
+        *self.indexed_bytes.read() as f64 / self.mmap.len() as f64
+    }
+
+    // the last line isn't finished until the whole file is indexed
+    pub fn get_line_count (&self) -> usize {
+        let line_starts_len = self.line_starts.read().len();
+        if self.is_done() {line_starts_len} else {line_starts_len - 1}
+    }
+
+    pub fn get_line_bytes (&self, line_num: usize) -> &[u8] {
+        let line_starts = self.line_starts.read();
+        let Some(start) = line_starts.get(line_num).copied() else {return &[];};
+        let end = match line_starts.get(line_num + 1) {
+            Some(next_start) => next_start - 1,
+            None => self.mmap.len(),
+        };
+        let line = &self.mmap[start..end];
+        line.strip_suffix(b"\r").unwrap_or(line)
+    }
+
+
+
+}
+
+
+
+
+
+#[derive(Debug)]
+pub struct LazyText {
+    pub line_index: Arc<LineIndex>,
+    pub encoding: TextEncoding,
+    segments: Vec<LazySegment>,
+}
+
+#[derive(Debug)]
+enum LazySegment {
+    Original {start: usize, end: Option<usize>}, // an end of None means "up to the last indexed line"
+    Edited (Vec<String>),
+}
+
+impl LazyText {
+
+
+
+    pub fn new (line_index: Arc<LineIndex>, encoding: TextEncoding) -> Self {
+        Self {
+            line_index,
+            encoding,
+            segments: vec!(LazySegment::Original {start: 0, end: None}),
+        }
+    }
+
+
+
+    pub fn len_lines (&self) -> usize {
+        self.segments.iter().map(|segment| self.get_segment_len(segment)).sum::<usize>().max(1)
+    }
+
+    fn get_segment_len (&self, segment: &LazySegment) -> usize {
+        match segment {
+            LazySegment::Original {start, end: Some(end)} => end - start,
+            LazySegment::Original {start, end: None} => self.line_index.get_line_count().saturating_sub(*start),
+            LazySegment::Edited(lines) => lines.len(),
+        }
+    }
+
+    pub fn line (&self, mut y: usize) -> String {
+        for segment in &self.segments {
+            let segment_len = self.get_segment_len(segment);
+            if y >= segment_len {
+                y -= segment_len;
+                continue;
+            }
+            return match segment {
+                LazySegment::Original {start, ..} => self.decode_line(start + y),
+                LazySegment::Edited(lines) => lines[y].to_string(),
+            };
+        }
+        String::new()
+    }
+
+    fn decode_line (&self, line_num: usize) -> String {
+        let bytes = self.line_index.get_line_bytes(line_num);
+        match self.encoding {
+            TextEncoding::Latin1 => bytes.iter().map(|byte| *byte as char).collect(),
+            _ => String::from_utf8_lossy(bytes).to_string(),
+        }
+    }
+
+
+
+    pub fn insert (&mut self, x: usize, y: usize, text: &str) {
+        let line = self.line(y).chars().collect::<Vec<char>>();
+        let new_text = chain!(line[..x].iter().copied(), text.chars(), line[x..].iter().copied()).collect::<String>();
+        self.splice_lines(y, y, new_text.split('\n').map(str::to_string).collect());
+    }
+
+    pub fn remove (&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let first_line = self.line(start.1).chars().collect::<Vec<char>>();
+        let last_line = self.line(end.1).chars().collect::<Vec<char>>();
+        let removed_text = self.slice(start, end);
+        let new_line = chain!(first_line[..start.0].iter(), last_line[end.0..].iter()).collect::<String>();
+        self.splice_lines(start.1, end.1, vec!(new_line));
+        removed_text
+    }
+
+    pub fn slice (&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let mut output = String::new();
+        for y in start.1..=end.1 {
+            let line = self.line(y).chars().collect::<Vec<char>>();
+            let line_start = if y == start.1 {start.0} else {0};
+            let line_end = if y == end.1 {end.0} else {line.len()};
+            output.extend(&line[line_start..line_end]);
+            if y != end.1 {output.push('\n');}
+        }
+        output
+    }
+
+
+
+    // replaces lines first_y to last_y (inclusive) with the new lines
+    fn splice_lines (&mut self, first_y: usize, last_y: usize, new_lines: Vec<String>) {
+        let mut new_segments = vec!();
+        let mut segment_start_y = 0;
+        let mut new_lines = Some(new_lines);
+
+        let old_segments = self.segments.drain(..).collect::<Vec<LazySegment>>();
+        for segment in old_segments {
+            let segment_len = self.get_segment_len(&segment);
+            let segment_end_y = segment_start_y + segment_len;
+            if segment_start_y > last_y || segment_end_y <= first_y {
+                new_segments.push(segment);
+                segment_start_y = segment_end_y;
+                continue;
+            }
+
+            // keep the parts of this segment that are before and after the spliced lines
+            let keep_before = first_y.saturating_sub(segment_start_y);
+            let keep_after_start = (last_y + 1 - segment_start_y).min(segment_len);
+            match segment {
+                LazySegment::Original {start, end} => {
+                    if keep_before > 0 {
+                        new_segments.push(LazySegment::Original {start, end: Some(start + keep_before)});
+                    }
+                    if let Some(new_lines) = new_lines.take() {
+                        new_segments.push(LazySegment::Edited(new_lines));
+                    }
+                    if end.is_none() || keep_after_start < segment_len {
+                        new_segments.push(LazySegment::Original {start: start + keep_after_start, end});
+                    }
+                }
+                LazySegment::Edited(mut lines) => {
+                    let lines_after = lines.split_off(keep_after_start);
+                    lines.truncate(keep_before);
+                    if let Some(new_lines) = new_lines.take() {
+                        lines.extend(new_lines);
+                    }
+                    lines.extend(lines_after);
+                    if !lines.is_empty() {
+                        new_segments.push(LazySegment::Edited(lines));
+                    }
+                }
+            }
+
+            segment_start_y = segment_end_y;
+        }
+
+        // the spliced lines can be past the end while the file is empty or still being indexed
+        if let Some(new_lines) = new_lines {
+            new_segments.push(LazySegment::Edited(new_lines));
+        }
+        self.segments = new_segments;
+        self.merge_edited_segments();
+    }
+
+    fn merge_edited_segments (&mut self) {
+        let mut merged_segments: Vec<LazySegment> = vec!();
+        for segment in self.segments.drain(..) {
+            match (merged_segments.last_mut(), segment) {
+                (Some(LazySegment::Edited(last_lines)), LazySegment::Edited(lines)) => last_lines.extend(lines),
+                (_, segment) => merged_segments.push(segment),
+            }
+        }
+        self.segments = merged_segments;
+    }
+
+
+
+}
+
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use memmap2::MmapMut;
+
+    fn new_line_index (bytes: &[u8]) -> LineIndex {
+        let mut mmap = MmapMut::map_anon(bytes.len()).unwrap();
+        mmap.copy_from_slice(bytes);
+        let line_index = LineIndex::new(mmap.make_read_only().unwrap(), true);
+        while !line_index.index_next_chunk() {}
+        line_index
+    }
+
+    fn new_lazy_text (text: &str) -> LazyText {
+        LazyText::new(Arc::new(new_line_index(text.as_bytes())), TextEncoding::Utf8)
+    }
+
+    fn get_text (lazy_text: &LazyText) -> String {
+        (0..lazy_text.len_lines()).map(|y| lazy_text.line(y)).collect::<Vec<String>>().join("\n")
+    }
+
+    #[test]
+    fn lines_are_read_from_the_mapped_file () {
+        let lazy_text = new_lazy_text("one\r\ntwo\nthree");
+        assert_eq!(lazy_text.len_lines(), 3);
+        assert_eq!(lazy_text.line(0), "one");
+        assert_eq!(lazy_text.line(2), "three");
+        assert_eq!(lazy_text.line(3), "");
+    }
+
+    #[test]
+    fn insert_splits_the_original_lines () {
+        let mut lazy_text = new_lazy_text("a\nb\nc\nd");
+        lazy_text.insert(1, 1, "X\nY");
+        assert_eq!(get_text(&lazy_text), "a\nbX\nY\nc\nd");
+        lazy_text.insert(1, 4, "!");
+        assert_eq!(get_text(&lazy_text), "a\nbX\nY\nc\nd!");
+        lazy_text.insert(0, 0, "\n");
+        assert_eq!(get_text(&lazy_text), "\na\nbX\nY\nc\nd!");
+    }
+
+    #[test]
+    fn remove_across_edited_and_original_lines () {
+        let mut lazy_text = new_lazy_text("a\nb\nc\nd\ne");
+        lazy_text.insert(1, 1, "X\nY");
+        assert_eq!(lazy_text.remove((1, 0), (1, 3)), "\nbX\nY\nc");
+        assert_eq!(get_text(&lazy_text), "a\nd\ne");
+        assert_eq!(lazy_text.remove((0, 0), (1, 2)), "a\nd\ne");
+        assert_eq!(get_text(&lazy_text), "");
+        assert_eq!(lazy_text.len_lines(), 1);
+    }
+
+    #[test]
+    fn invalid_utf8_is_found_in_every_chunk () {
+        let mut bytes = vec![b'a'; LINE_INDEX_CHUNK_SIZE - 1];
+        bytes.extend("\u{e9}\n".as_bytes());
+        assert!(!*new_line_index(&bytes).has_invalid_utf8.read());
+        bytes.push(0xFF);
+        assert!(*new_line_index(&bytes).has_invalid_utf8.read());
+    }
+
+}
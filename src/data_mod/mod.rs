@@ -1,3 +1,6 @@
 pub mod program_data;
 pub mod settings;
 pub mod errors;
+pub mod text_buffer;
+pub mod lazy_text;
+pub mod edit_history;
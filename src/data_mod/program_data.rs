@@ -1,5 +1,8 @@
 use crate::prelude::*;
-use sdl2::{render::Texture};
+use sdl2::{render::Texture, rect::Rect};
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use memmap2::MmapMut;
 
 
 
@@ -26,6 +29,11 @@ pub struct ProgramData {
     pub current_file_num: RwLock<Option<usize>>,
       #[default(RwLock::new(Instant::now()))]
     pub cursor_place_instant: RwLock<Instant>,
+    pub show_history_panel: RwLock<bool>,
+    pub soft_wrap: RwLock<bool>,
+    pub wrap_width: RwLock<usize>, // in columns, this is updated by the renderer to fit the text section
+    pub text_section: RwLock<Option<Rect>>, // also updated by the renderer, so mouse positions can be mapped to the text
+    pub mouse_state: RwLock<MouseState>,
 
 }
 
@@ -35,7 +43,8 @@ pub struct ProgramData {
 
 #[derive(fmt_derive::Debug)]
 pub struct ProgramTextures<'a> {
-    pub ascii_chars: [Texture<'a>; 256]
+    pub ascii_chars: [Texture<'a>; 256],
+    pub other_graphemes: HashMap<String, Texture<'a>>, // filled in as they're first rendered
 }
 
 
@@ -45,19 +54,29 @@ pub struct ProgramTextures<'a> {
 #[derive(Debug)]
 pub struct File {
     pub path: String,
-    pub contents: Vec<Vec<char>>,
+    pub contents: TextBuffer,
     pub scroll_x: f64,
     pub scroll_y: f64,
+    pub scroll_to_cursor: bool, // set when the cursors move, the next frame then scrolls so the last cursor is visible
     pub cursors: Vec<Cursor>,
+    pub history: EditHistory,
+    pub saved_contents_hash: Option<u64>,
+    pub line_ending: LineEnding,
+    pub has_mixed_line_endings: bool,
+    pub encoding: TextEncoding,
+    pub indentation: Indentation,
+    pub tab_width: usize,
+    pub hex_view: Option<HexView>, // binary files are edited as bytes instead of text
 }
 
 impl File {
-    pub fn new (path: String, contents: Vec<String>) -> Self {
+    pub fn new (path: String, contents: TextBuffer) -> Self {
         Self {
             path,
-            contents: contents.iter().map(|s| s.chars().collect()).collect(),
+            contents,
             scroll_x: 0.,
             scroll_y: 0.,
+            scroll_to_cursor: false,
             cursors: vec![
                 Cursor {
                     x: 0,
@@ -66,17 +85,259 @@ impl File {
                     selection_start: None,
                 }
             ],
+            history: EditHistory::default(),
+            saved_contents_hash: None,
+            line_ending: LineEnding::default(),
+            has_mixed_line_endings: false,
+            encoding: TextEncoding::Utf8,
+            indentation: Indentation::Spaces(4),
+            tab_width: 4,
+            hex_view: None,
         }
     }
 }
 
+impl File {
+
+
+
+    // every edit goes through these, and they move all the cursors so that they stay next to the same text
+
+    pub fn insert_text (&mut self, x: usize, y: usize, text: &str) -> (usize, usize) {
+        self.history.record_edit(Edit {
+            start: (x, y),
+            removed_text: String::new(),
+            inserted_text: text.to_string(),
+        });
+        let end = self.contents.insert(x, y, text);
+        self.move_cursors(|pos| fns::get_pos_after_insertion(pos, (x, y), end));
+        end
+    }
+
+    pub fn remove_text (&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let removed_text = self.contents.remove(start, end);
+        self.history.record_edit(Edit {
+            start,
+            removed_text: removed_text.clone(),
+            inserted_text: String::new(),
+        });
+        self.move_cursors(|pos| fns::get_pos_after_removal(pos, start, end));
+        removed_text
+    }
+
+    // moved cursors are usually on the same few lines, so the last line is kept instead of being read again for every cursor
+    pub fn move_cursors (&mut self, move_fn: impl Fn((usize, usize)) -> (usize, usize)) {
+        let mut last_line: Option<(usize, Vec<char>)> = None;
+        for cursor in &mut self.cursors {
+            let new_pos = move_fn((cursor.x, cursor.y));
+            // cursors that are only moved up or down (by an edit on another line) keep their column for up and down movement
+            let x_changed = new_pos.0 != cursor.x;
+            (cursor.x, cursor.y) = new_pos;
+            if x_changed {
+                if last_line.as_ref().map(|(y, _)| *y) != Some(new_pos.1) {
+                    last_line = Some((new_pos.1, self.contents.line(new_pos.1)));
+                }
+                if let Some((_, line)) = &last_line {
+                    cursor.wanted_x = fns::get_column(line, new_pos.0, self.tab_width);
+                }
+            }
+            cursor.selection_start = cursor.selection_start.map(&move_fn);
+        }
+    }
+
+
+
+    pub fn undo (&mut self) -> bool {
+        let new_cursors = self.history.undo(&mut self.contents);
+        self.set_cursors_from_history(new_cursors)
+    }
+
+    pub fn redo (&mut self) -> bool {
+        let new_cursors = self.history.redo(&mut self.contents);
+        self.set_cursors_from_history(new_cursors)
+    }
+
+    pub fn go_to_earlier_step (&mut self) -> bool {
+        let new_cursors = self.history.go_to_earlier_step(&mut self.contents);
+        self.set_cursors_from_history(new_cursors)
+    }
+
+    pub fn go_to_later_step (&mut self) -> bool {
+        let new_cursors = self.history.go_to_later_step(&mut self.contents);
+        self.set_cursors_from_history(new_cursors)
+    }
+
+    pub fn set_cursors_from_history (&mut self, new_cursors: Option<Vec<Cursor>>) -> bool {
+        let Some(new_cursors) = new_cursors else {return false;};
+        if !new_cursors.is_empty() {
+            self.cursors = new_cursors;
+        }
+        self.scroll_to_cursor = true;
+        true
+    }
+
+
+
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl Default for LineEnding {
+    fn default() -> Self {
+        if cfg!(windows) {Self::CrLf} else {Self::Lf}
+    }
+}
+
+impl LineEnding {
+    pub fn as_str (&self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::CrLf => "\r\n",
+        }
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indentation {
+    Tabs,
+    Spaces (usize),
+}
+
+impl Indentation {
+    pub fn get_width (&self, tab_width: usize) -> usize {
+        match self {
+            Self::Tabs => tab_width,
+            Self::Spaces(width) => *width,
+        }
+    }
+}
+
+
 
+pub const HEX_VIEW_ROW_LEN: usize = 16;
 
 #[derive(Debug)]
+pub struct HexView {
+    pub bytes: HexBytes,
+    pub cursor: usize,
+    pub editing_low_nibble: bool,
+    pub scroll_row: usize,
+}
+
+impl HexView {
+
+    pub fn new (bytes: HexBytes) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            editing_low_nibble: false,
+            scroll_row: 0,
+        }
+    }
+
+    pub fn is_mapped (&self) -> bool {
+        matches!(self.bytes, HexBytes::Mapped(_))
+    }
+
+    pub fn move_cursor (&mut self, amount: isize) {
+        let max_cursor = self.bytes.len().max(1) - 1;
+        self.cursor = self.cursor.saturating_add_signed(amount).min(max_cursor);
+        self.editing_low_nibble = false;
+    }
+
+    // mapped bytes can only be overwritten, since removing one would mean copying everything after it
+    pub fn remove_byte (&mut self, index: usize) {
+        let HexBytes::Loaded(bytes) = &mut self.bytes else {return;};
+        if index >= bytes.len() {return;}
+        bytes.remove(index);
+        self.cursor = self.cursor.min(self.bytes.len().max(1) - 1);
+        self.editing_low_nibble = false;
+    }
+
+    // overwrites half of the byte at the cursor, then moves to the next byte once both halves are typed
+    pub fn type_hex_digit (&mut self, digit: u8) {
+        let Some(byte) = self.bytes.get_mut(self.cursor) else {return;};
+        if self.editing_low_nibble {
+            *byte = (*byte & 0xF0) | digit;
+            self.move_cursor(1);
+        } else {
+            *byte = (*byte & 0x0F) | (digit << 4);
+            self.editing_low_nibble = true;
+        }
+    }
+
+}
+
+
+
+// large files are mapped copy-on-write, so edits stay in memory until the file is saved
+#[derive(Debug)]
+pub enum HexBytes {
+    Loaded (Vec<u8>),
+    Mapped (MmapMut),
+}
+
+impl Deref for HexBytes {
+    type Target = [u8];
+    fn deref (&self) -> &[u8] {
+        match self {
+            Self::Loaded(bytes) => bytes,
+            Self::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+impl DerefMut for HexBytes {
+    fn deref_mut (&mut self) -> &mut [u8] {
+        match self {
+            Self::Loaded(bytes) => bytes,
+            Self::Mapped(mmap) => mmap,
+        }
+    }
+}
+
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEncoding {
+    Utf8,
+    Utf8Bom,
+    Utf16Le,
+    Utf16LeBom,
+    Utf16Be,
+    Utf16BeBom,
+    Latin1,
+}
+
+impl TextEncoding {
+    pub fn next (&self) -> Self {
+        match self {
+            Self::Utf8 => Self::Utf8Bom,
+            Self::Utf8Bom => Self::Utf16Le,
+            Self::Utf16Le => Self::Utf16LeBom,
+            Self::Utf16LeBom => Self::Utf16Be,
+            Self::Utf16Be => Self::Utf16BeBom,
+            Self::Utf16BeBom => Self::Latin1,
+            Self::Latin1 => Self::Utf8,
+        }
+    }
+}
+
+
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Cursor {
     pub x: usize,
     pub y: usize,
-    pub wanted_x: usize,
+    pub wanted_x: usize, // this is a column, not a char index
     pub selection_start: Option<(usize, usize)>,
 }
 
@@ -84,7 +345,7 @@ pub struct Cursor {
 
 
 
-#[derive(Debug, Default)]
+#[derive(Debug, Default, Clone, Copy)]
 pub struct KeysPressed {
     pub shift_pressed: bool,
     pub control_pressed: bool,
@@ -105,8 +366,33 @@ impl KeysPressed {
 
 
 
+#[derive(Debug, Clone, Copy, SmartDefault)]
+pub struct MouseState {
+    pub x: i32,
+    pub y: i32,
+    pub drag_start: Option<(usize, usize)>, // the text position that a drag selects from, set while the left button is held
+    pub box_selection_start: Option<(usize, usize)>, // (column, line), set while alt-dragging
+      #[default(Instant::now())]
+    pub last_auto_scroll_instant: Instant,
+}
+
+
+
+
+
 #[derive(Debug)]
 pub enum ProgramTask {
     LoadFile {file_path: String, switch_to_this: bool},
     SaveFile {file_num: usize, file_path: String},
+    ReloadWithEncoding {file_num: usize, encoding: TextEncoding},
+    IndexLines {line_index: Arc<LineIndex>},
+    FilterText {file_num: usize, file_path: String, command: String, ranges: Vec<FilterRange>},
+}
+
+#[derive(Debug)]
+pub struct FilterRange {
+    pub cursor_num: Option<usize>, // None when the whole file is filtered
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub text: String,
 }
@@ -18,11 +18,36 @@ pub struct ProgramSettings {
     pub cursor_height: f64, // relative to font height
     pub cursor_color: Color,
     pub cursor_selection_color: Color,
+    pub bracket_highlight_color: Color,
+
+    pub tab_width: usize,
+    pub insert_spaces: bool, // only used when a file's indentation can't be detected
+    pub soft_wrap: bool,
+    pub reflow_column: usize,
+    pub sort_regex: Regex, // lines are sorted by the first capture group (or the whole match) when sorting by regex
+    pub filter_commands: Vec<String>, // run with ctrl+alt+1 through ctrl+alt+9
+
+    pub large_file_threshold: u64, // in bytes, larger files are loaded lazily
+
+    pub languages: Vec<LanguageSettings>,
+    pub default_language: LanguageSettings, // used for files that don't match any language's extensions
 
     pub continue_details: ContinueDetails,
 
 }
 
+impl ProgramSettings {
+    pub fn get_language (&self, file_path: &str) -> &LanguageSettings {
+        self.get_known_language(file_path).unwrap_or(&self.default_language)
+    }
+    // returns None for files that don't match any language's extensions
+    pub fn get_known_language (&self, file_path: &str) -> Option<&LanguageSettings> {
+        let extension = file_path.rsplit_once('.').map_or("", |(_, extension)| extension);
+        self.languages.iter()
+            .find(|language| language.extensions.iter().any(|language_extension| language_extension == extension))
+    }
+}
+
 impl Default for ProgramSettings {
     fn default() -> Self {
         Self {
@@ -39,6 +64,82 @@ impl Default for ProgramSettings {
             cursor_height: 1.1,
             cursor_color: Color::RGB(255, 255, 255),
             cursor_selection_color: Color::RGBA(0, 31, 255, 127),
+            bracket_highlight_color: Color::RGBA(255, 255, 255, 63),
+
+            tab_width: 4,
+            insert_spaces: true,
+            soft_wrap: false,
+            reflow_column: 80,
+            sort_regex: Regex::new(r"^\s*(.*)").unwrap(),
+            filter_commands: vec!(String::from("sort"), String::from("jq ."), String::from("rustfmt")),
+
+            large_file_threshold: 64 * 1024 * 1024,
+
+            languages: vec!(
+                LanguageSettings {
+                    name: String::from("rust"),
+                    extensions: vec!(String::from("rs")),
+                    auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')), // no single quotes, since they're also used for lifetimes
+                    line_comment: Some(String::from("//")),
+                    block_comment: Some((String::from("/*"), String::from("*/"))),
+                    string_delimiters: vec!('"'),
+                    char_literal: Some('\''),
+                },
+                LanguageSettings {
+                    name: String::from("c"),
+                    extensions: vec!(String::from("c"), String::from("h"), String::from("cpp"), String::from("hpp"), String::from("cs"), String::from("java"), String::from("js"), String::from("ts"), String::from("go")),
+                    auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')),
+                    line_comment: Some(String::from("//")),
+                    block_comment: Some((String::from("/*"), String::from("*/"))),
+                    string_delimiters: vec!('"'),
+                    char_literal: Some('\''),
+                },
+                LanguageSettings {
+                    name: String::from("shell"),
+                    extensions: vec!(String::from("sh"), String::from("bash"), String::from("zsh")),
+                    auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')),
+                    line_comment: Some(String::from("#")),
+                    block_comment: None,
+                    string_delimiters: vec!('"', '\''),
+                    char_literal: None,
+                },
+                LanguageSettings {
+                    name: String::from("python"),
+                    extensions: vec!(String::from("py")),
+                    auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')),
+                    line_comment: Some(String::from("#")),
+                    block_comment: Some((String::from("\"\"\""), String::from("\"\"\""))),
+                    string_delimiters: vec!('"', '\''),
+                    char_literal: None,
+                },
+                LanguageSettings {
+                    name: String::from("hjson"),
+                    extensions: vec!(String::from("hjson"), String::from("toml"), String::from("yaml"), String::from("yml")),
+                    auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')),
+                    line_comment: Some(String::from("#")),
+                    block_comment: None,
+                    string_delimiters: vec!('"', '\''),
+                    char_literal: None,
+                },
+                LanguageSettings {
+                    name: String::from("html"),
+                    extensions: vec!(String::from("html"), String::from("xml"), String::from("md")),
+                    auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')),
+                    line_comment: None,
+                    block_comment: Some((String::from("<!--"), String::from("-->"))),
+                    string_delimiters: vec!(),
+                    char_literal: None,
+                },
+            ),
+            default_language: LanguageSettings {
+                name: String::from("default"),
+                extensions: vec!(),
+                auto_pairs: vec!(('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')),
+                line_comment: None,
+                block_comment: None,
+                string_delimiters: vec!(),
+                char_literal: None,
+            },
 
             continue_details: ContinueDetails {
                 last_open_files: vec!(),
@@ -56,6 +157,28 @@ pub trait SettingValueEnum {
 
 
 
+#[derive(Debug, Clone)]
+pub struct LanguageSettings {
+    pub name: String,
+    pub extensions: Vec<String>,
+    pub auto_pairs: Vec<(char, char)>, // (opener, closer)
+    pub line_comment: Option<String>,
+    pub block_comment: Option<(String, String)>, // (start, end)
+    pub string_delimiters: Vec<char>,
+    pub char_literal: Option<char>, // for single chars like 'x', which isn't a string delimiter since it can also be a lifetime
+}
+
+impl LanguageSettings {
+    pub fn get_closer (&self, opener: char) -> Option<char> {
+        self.auto_pairs.iter().find(|(pair_opener, _)| *pair_opener == opener).map(|(_, closer)| *closer)
+    }
+    pub fn is_closer (&self, char: char) -> bool {
+        self.auto_pairs.iter().any(|(_, closer)| *closer == char)
+    }
+}
+
+
+
 #[derive(Debug)]
 pub struct ContinueDetails {
     pub last_open_files: Vec<String>,
@@ -86,8 +209,29 @@ impl SettingValueEnum for FrameTimingSetting {
 
 type SettingsUpdaterFn = dyn Fn(&mut Map<String, Value>);
 
-const SETTINGS_UPDATER_FNS: [&SettingsUpdaterFn; 1] = [
-    /* 0 */ &|_| {
+const SETTINGS_UPDATER_FNS: [&SettingsUpdaterFn; 2] = [
+    /* 0 */ &|settings| {
+        // adds the editing settings with their default values, languages that aren't given use the built-in ones
+        let default_settings = ProgramSettings::default();
+        let new_settings = [
+            ("bracket highlight color", Value::U64(fns::color_to_u64(default_settings.bracket_highlight_color))),
+            ("tab width", Value::U64(default_settings.tab_width as u64)),
+            ("insert spaces", Value::Bool(default_settings.insert_spaces)),
+            ("soft wrap", Value::Bool(default_settings.soft_wrap)),
+            ("reflow column", Value::U64(default_settings.reflow_column as u64)),
+            ("sort regex", Value::String(default_settings.sort_regex.as_str().to_string())),
+            ("filter commands", Value::Array(default_settings.filter_commands.iter().cloned().map(Value::String).collect())),
+            ("large file threshold", Value::U64(default_settings.large_file_threshold)),
+            ("languages", Value::Object(Map::new())),
+        ];
+        for (key, value) in new_settings {
+            if settings.get(key).is_none() {
+                settings.insert(key.to_string(), value);
+            }
+        }
+        settings.insert(String::from("settings version"), Value::U64(1));
+    },
+    /* 1 */ &|_| {
         println!("Settings are up to date");
     },
 ];
@@ -212,6 +356,39 @@ fn get_settings_from_hjson (settings: Map<String, Value>, default_settings: &Pro
         }
     };
 
+    let sort_regex = get_setting_borrowed(&settings, "sort regex", Value::as_str, "string", default_settings.sort_regex.as_str());
+    let sort_regex = Regex::new(sort_regex).unwrap_or_else(|error| {
+        println!("Warning: invalid setting value for \"sort regex\", {error}");
+        default_settings.sort_regex.clone()
+    });
+
+    // languages are given as "languages/<name>/...", and the one named "default" is used for unknown files
+    // the given languages replace the built-in ones with the same name, and new ones are checked before the built-in ones
+    let mut languages = default_settings.languages.clone();
+    let mut new_languages = vec!();
+    let mut default_language = default_settings.default_language.clone();
+    let language_names = match fns::get_hjson_value(&settings, "languages") {
+        Some(Value::Object(languages)) => languages.keys().cloned().collect(),
+        _ => {
+            println!("Warning: could not find setting \"languages\"");
+            vec!()
+        }
+    };
+    for language_name in language_names {
+        let language_defaults = default_settings.languages.iter()
+            .find(|language| language.name == language_name)
+            .unwrap_or(&default_settings.default_language);
+        let language = get_language_settings(&settings, &language_name, language_defaults);
+        if language_name == "default" {
+            default_language = language;
+        } else if let Some(existing_language) = languages.iter_mut().find(|existing_language| existing_language.name == language_name) {
+            *existing_language = language;
+        } else {
+            new_languages.push(language);
+        }
+    }
+    let languages = new_languages.into_iter().chain(languages).collect();
+
     Ok(ProgramSettings {
 
         frame_timing,
@@ -226,6 +403,19 @@ fn get_settings_from_hjson (settings: Map<String, Value>, default_settings: &Pro
         cursor_height: get_setting(&settings, "cursor height", Value::as_f64, "f64", default_settings.cursor_height),
         cursor_color: get_setting_color(&settings, "cursor color", default_settings.cursor_color),
         cursor_selection_color: get_setting_color(&settings, "cursor selection color", default_settings.cursor_selection_color),
+        bracket_highlight_color: get_setting_color(&settings, "bracket highlight color", default_settings.bracket_highlight_color),
+
+        tab_width: get_setting(&settings, "tab width", Value::as_u64, "u64", default_settings.tab_width as u64).max(1) as usize,
+        insert_spaces: get_setting(&settings, "insert spaces", Value::as_bool, "bool", default_settings.insert_spaces),
+        soft_wrap: get_setting(&settings, "soft wrap", Value::as_bool, "bool", default_settings.soft_wrap),
+        reflow_column: get_setting(&settings, "reflow column", Value::as_u64, "u64", default_settings.reflow_column as u64) as usize,
+        sort_regex,
+        filter_commands: get_setting_string_array(&settings, "filter commands", default_settings.filter_commands.clone()),
+
+        large_file_threshold: get_setting(&settings, "large file threshold", Value::as_u64, "u64", default_settings.large_file_threshold),
+
+        languages,
+        default_language,
 
         continue_details: ContinueDetails {
             last_open_files: get_setting_string_array(&settings, "continue details/last open files", vec!()),
@@ -238,6 +428,63 @@ fn get_settings_from_hjson (settings: Map<String, Value>, default_settings: &Pro
 
 
 
+pub fn get_language_settings (settings: &Map<String, Value>, language_name: &str, default_language: &LanguageSettings) -> LanguageSettings {
+    let key_start = format!("languages/{language_name}");
+    let default_auto_pairs = default_language.auto_pairs.iter().map(|(opener, closer)| format!("{opener}{closer}")).collect();
+    let auto_pairs = get_setting_string_array(settings, &format!("{key_start}/auto pairs"), default_auto_pairs).iter()
+        .filter_map(|pair| {
+            let pair = pair.chars().collect::<Vec<char>>();
+            if pair.len() != 2 {
+                println!("Warning: invalid auto pair in \"{key_start}/auto pairs\", each pair needs to be exactly two characters");
+                return None;
+            }
+            Some((pair[0], pair[1]))
+        })
+        .collect();
+
+    // an empty string means the language doesn't have that kind of comment
+    let line_comment = match get_setting_defaultless(settings, &format!("{key_start}/line comment"), |value| value.as_str().map(str::to_string), "string") {
+        Some(line_comment) => Some(line_comment).filter(|line_comment| !line_comment.is_empty()),
+        None => default_language.line_comment.clone(),
+    };
+    let default_block_comment = default_language.block_comment.iter().flat_map(|(start, end)| [start.to_string(), end.to_string()]).collect();
+    let block_comment = get_setting_string_array(settings, &format!("{key_start}/block comment"), default_block_comment);
+    let block_comment = match block_comment.as_slice() {
+        [] => None,
+        [start, end] if !start.is_empty() && !end.is_empty() => Some((start.to_string(), end.to_string())),
+        _ => {
+            println!("Warning: invalid setting value for \"{key_start}/block comment\", it needs to be an array of two non-empty strings");
+            default_language.block_comment.clone()
+        }
+    };
+    let string_delimiters = get_setting(settings, &format!("{key_start}/string delimiters"), |value| value.as_str().map(|delimiters| delimiters.chars().collect()), "string", default_language.string_delimiters.clone());
+    let char_literal = match get_setting_defaultless(settings, &format!("{key_start}/char literal"), |value| value.as_str().map(|char_literal| char_literal.chars().collect::<Vec<char>>()), "string") {
+        Some(char_literal) => match char_literal.as_slice() {
+            [] => None,
+            [char_literal] => Some(*char_literal),
+            _ => {
+                println!("Warning: invalid setting value for \"{key_start}/char literal\", it needs to be at most one character");
+                default_language.char_literal
+            }
+        },
+        None => default_language.char_literal,
+    };
+
+    LanguageSettings {
+        name: language_name.to_string(),
+        extensions: if language_name == "default" {vec!()} else {get_setting_string_array(settings, &format!("{key_start}/extensions"), default_language.extensions.clone())},
+        auto_pairs,
+        line_comment,
+        block_comment,
+        string_delimiters,
+        char_literal,
+    }
+}
+
+
+
+
+
 pub fn get_setting<T> (settings: &Map<String, Value>, full_key: &str, value_fn: impl FnOnce(&Value) -> Option<T>, value_type_name: &str, default_value: T) -> T {
 
     let found_value = match fns::get_hjson_value(settings, full_key) {
@@ -344,3 +591,40 @@ pub fn get_setting_color (settings: &Map<String, Value>, full_key: &str, default
         None => default_value
     }
 }
+
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn old_settings_get_new_keys () {
+        let settings = serde_hjson::from_str("{\"settings version\": 0, \"tab width\": 8}").unwrap();
+        let settings = update_settings(settings).unwrap();
+        assert_eq!(settings.get("settings version").and_then(Value::as_u64), Some(1));
+        assert_eq!(settings.get("tab width").and_then(Value::as_u64), Some(8));
+        assert_eq!(settings.get("reflow column").and_then(Value::as_u64), Some(80));
+        assert!(settings.get("languages").is_some_and(Value::is_object));
+    }
+
+    #[test]
+    fn languages_are_merged_with_the_built_in_ones () {
+        let raw_settings = "{
+            \"settings version\": 1,
+            \"languages\": {
+                \"rust\": {\"extensions\": [\"rs\", \"ron\"]},
+                \"markdown\": {\"extensions\": [\"md\"], \"block comment\": []},
+            },
+        }";
+        let settings = process_settings(raw_settings, &ProgramSettings::default()).unwrap();
+        assert_eq!(settings.get_language("a.ron").name, "rust");
+        assert_eq!(settings.get_language("a.ron").line_comment.as_deref(), Some("//"));
+        assert_eq!(settings.get_language("a.py").name, "python");
+        assert_eq!(settings.get_language("a.md").name, "markdown");
+        assert_eq!(settings.get_language("a.md").block_comment, None);
+        assert_eq!(settings.get_language("a.unknown").name, "default");
+    }
+
+}
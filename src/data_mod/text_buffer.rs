@@ -0,0 +1,272 @@
+use crate::prelude::*;
+use ropey::Rope;
+
+
+
+// positions are always (x, y), where x is a char index into line y
+
+
+
+#[derive(Debug)]
+pub struct TextBuffer {
+    storage: BufferStorage,
+}
+
+#[derive(Debug)]
+enum BufferStorage {
+    Rope (Rope),
+    Lazy (LazyText),
+}
+
+impl Default for TextBuffer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TextBuffer {
+
+
+
+    pub fn new () -> Self {
+        Self {
+            storage: BufferStorage::Rope(Rope::new()),
+        }
+    }
+
+    // the text should only use "\n" for line endings
+    pub fn from_text (text: &str) -> Self {
+        Self {
+            storage: BufferStorage::Rope(Rope::from_str(text)),
+        }
+    }
+
+    pub fn from_lazy_text (lazy_text: LazyText) -> Self {
+        Self {
+            storage: BufferStorage::Lazy(lazy_text),
+        }
+    }
+
+    pub fn is_lazy (&self) -> bool {
+        matches!(self.storage, BufferStorage::Lazy(_))
+    }
+
+    pub fn get_line_index (&self) -> Option<&Arc<LineIndex>> {
+        match &self.storage {
+            BufferStorage::Lazy(lazy_text) => Some(&lazy_text.line_index),
+            _ => None,
+        }
+    }
+
+    // returns None when there is nothing left to index
+    pub fn get_indexing_progress (&self) -> Option<f64> {
+        match &self.storage {
+            BufferStorage::Lazy(lazy_text) if !lazy_text.line_index.is_done() => Some(lazy_text.line_index.get_progress()),
+            _ => None,
+        }
+    }
+
+
+
+    pub fn len_lines (&self) -> usize {
+        match &self.storage {
+            BufferStorage::Rope(rope) => rope.len_lines(),
+            BufferStorage::Lazy(lazy_text) => lazy_text.len_lines(),
+        }
+    }
+
+    // this has to decode the entire file for lazy buffers
+    pub fn len_chars (&self) -> usize {
+        match &self.storage {
+            BufferStorage::Rope(rope) => rope.len_chars(),
+            BufferStorage::Lazy(_) => {
+                let (end_x, end_y) = self.end_pos();
+                self.pos_to_char_index(end_x, end_y)
+            }
+        }
+    }
+
+    pub fn line_len (&self, y: usize) -> usize {
+        match &self.storage {
+            BufferStorage::Rope(rope) => {
+                let line = rope.line(y);
+                let len = line.len_chars();
+                if len > 0 && line.char(len - 1) == '\n' {len - 1} else {len}
+            }
+            BufferStorage::Lazy(lazy_text) => lazy_text.line(y).chars().count(),
+        }
+    }
+
+    pub fn line (&self, y: usize) -> Vec<char> {
+        match &self.storage {
+            BufferStorage::Rope(rope) => {
+                let mut output = rope.line(y).chars().collect::<Vec<char>>();
+                if output.last() == Some(&'\n') {output.pop();}
+                output
+            }
+            BufferStorage::Lazy(lazy_text) => lazy_text.line(y).chars().collect(),
+        }
+    }
+
+    // this doesn't allocate the whole line for rope buffers, so it should be used when only part of a line is needed
+    pub fn line_chars (&self, y: usize) -> Box<dyn Iterator<Item = char> + '_> {
+        match &self.storage {
+            BufferStorage::Rope(rope) => Box::new(rope.line(y).chars().take_while(|char| *char != '\n')),
+            BufferStorage::Lazy(lazy_text) => Box::new(lazy_text.line(y).chars().collect::<Vec<char>>().into_iter()),
+        }
+    }
+
+    pub fn lines (&self) -> impl Iterator<Item = Vec<char>> + '_ {
+        (0..self.len_lines()).map(|y| self.line(y))
+    }
+
+    pub fn char_at (&self, x: usize, y: usize) -> Option<char> {
+        if y >= self.len_lines() {return None;}
+        match &self.storage {
+            BufferStorage::Rope(rope) => rope.line(y).get_char(x).filter(|char| *char != '\n'),
+            BufferStorage::Lazy(_) => self.line_chars(y).nth(x),
+        }
+    }
+
+    pub fn end_pos (&self) -> (usize, usize) {
+        let last_y = self.len_lines() - 1;
+        (self.line_len(last_y), last_y)
+    }
+
+
+
+    // cursors move by grapheme clusters, so these never return a position inside of one
+
+    pub fn get_prev_grapheme_boundary (&self, x: usize, y: usize) -> usize {
+        let boundaries = fns::get_grapheme_boundaries(&self.line(y));
+        boundaries.into_iter().rev().find(|boundary| *boundary < x).unwrap_or(0)
+    }
+
+    pub fn get_next_grapheme_boundary (&self, x: usize, y: usize) -> usize {
+        let line = self.line(y);
+        let boundaries = fns::get_grapheme_boundaries(&line);
+        boundaries.into_iter().find(|boundary| *boundary > x).unwrap_or(line.len())
+    }
+
+    // columns are where the text is shown on screen, which is different from x once there are wide characters
+    pub fn get_column (&self, x: usize, y: usize, tab_width: usize) -> usize {
+        fns::get_column(&self.line_chars(y).take(x).collect::<Vec<char>>(), x, tab_width)
+    }
+
+    pub fn get_x_at_column (&self, column: usize, y: usize, tab_width: usize) -> usize {
+        fns::get_x_at_column(&self.line(y), column, tab_width)
+    }
+
+
+
+    // these have to decode everything before the position for lazy buffers
+
+    pub fn pos_to_char_index (&self, x: usize, y: usize) -> usize {
+        match &self.storage {
+            BufferStorage::Rope(rope) => rope.line_to_char(y) + x,
+            BufferStorage::Lazy(_) => (0..y).map(|y| self.line_len(y) + 1).sum::<usize>() + x,
+        }
+    }
+
+    pub fn char_index_to_pos (&self, mut char_index: usize) -> (usize, usize) {
+        match &self.storage {
+            BufferStorage::Rope(rope) => {
+                let y = rope.char_to_line(char_index);
+                (char_index - rope.line_to_char(y), y)
+            }
+            BufferStorage::Lazy(_) => {
+                for y in 0..self.len_lines() {
+                    let line_len = self.line_len(y);
+                    if char_index <= line_len {return (char_index, y);}
+                    char_index -= line_len + 1;
+                }
+                self.end_pos()
+            }
+        }
+    }
+
+
+
+    // returns the position right after the inserted text
+    pub fn insert (&mut self, x: usize, y: usize, text: &str) -> (usize, usize) {
+        match &mut self.storage {
+            BufferStorage::Rope(rope) => {
+                let char_index = rope.line_to_char(y) + x;
+                rope.insert(char_index, text);
+            }
+            BufferStorage::Lazy(lazy_text) => lazy_text.insert(x, y, text),
+        }
+        fns::get_text_end_pos((x, y), text)
+    }
+
+    // returns the removed text
+    pub fn remove (&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        match &mut self.storage {
+            BufferStorage::Rope(rope) => {
+                let start_index = rope.line_to_char(start.1) + start.0;
+                let end_index = rope.line_to_char(end.1) + end.0;
+                let removed_text = rope.slice(start_index..end_index).to_string();
+                rope.remove(start_index..end_index);
+                removed_text
+            }
+            BufferStorage::Lazy(lazy_text) => lazy_text.remove(start, end),
+        }
+    }
+
+    pub fn slice (&self, start: (usize, usize), end: (usize, usize)) -> String {
+        match &self.storage {
+            BufferStorage::Rope(rope) => {
+                let start_index = rope.line_to_char(start.1) + start.0;
+                let end_index = rope.line_to_char(end.1) + end.0;
+                rope.slice(start_index..end_index).to_string()
+            }
+            BufferStorage::Lazy(lazy_text) => lazy_text.slice(start, end),
+        }
+    }
+
+
+
+    // writes the text line by line, so lazy buffers don't have to be decoded all at once
+    pub fn write_lines (&self, mut write_fn: impl FnMut(&str, bool) -> Result<(), ProgramError>) -> Result<(), ProgramError> {
+        match &self.storage {
+            BufferStorage::Rope(rope) => {
+                let last_line_num = rope.len_lines() - 1;
+                for (i, line) in rope.lines().enumerate() {
+                    let line = line.to_string();
+                    write_fn(line.strip_suffix('\n').unwrap_or(&line), i == last_line_num)?;
+                }
+            }
+            BufferStorage::Lazy(lazy_text) => {
+                let len_lines = lazy_text.len_lines();
+                for y in 0..len_lines {
+                    write_fn(&lazy_text.line(y), y == len_lines - 1)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+
+
+}
+
+
+
+impl fmt::Display for TextBuffer {
+    fn fmt (&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.storage {
+            BufferStorage::Rope(rope) => {
+                for chunk in rope.chunks() {
+                    f.write_str(chunk)?;
+                }
+            }
+            BufferStorage::Lazy(lazy_text) => {
+                for y in 0..lazy_text.len_lines() {
+                    if y > 0 {f.write_str("\n")?;}
+                    f.write_str(&lazy_text.line(y))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
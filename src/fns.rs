@@ -1,5 +1,8 @@
 use crate::prelude::*;
-use std::{path::PathBuf, fs::OpenOptions};
+use std::{path::PathBuf, fs::OpenOptions, time::SystemTime};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+use std::{cmp::Ordering, iter::Peekable, str::Chars};
 use sdl2::{rect::Rect, pixels::Color, surface::Surface, video::WindowContext,
     render::{Texture, TextureCreator}
 };
@@ -42,22 +45,536 @@ pub fn get_file_exists (path: &PathBuf) -> Result<bool, IoError> {
 
 
 
-pub fn split_lines (full_str: &str) -> Vec<String> {
-    Regex::new("((\r\n)|\n)").unwrap().split(full_str)
-        .map(|s| s.to_string())
-        .collect()
+// returns the most common line ending and whether both kinds were found
+pub fn get_line_endings (full_str: &str) -> (LineEnding, bool) {
+    let crlf_count = full_str.matches("\r\n").count();
+    let lf_count = full_str.matches('\n').count() - crlf_count;
+    let line_ending = match (lf_count, crlf_count) {
+        (0, 0) => LineEnding::default(),
+        _ if crlf_count > lf_count => LineEnding::CrLf,
+        _ => LineEnding::Lf,
+    };
+    (line_ending, lf_count > 0 && crlf_count > 0)
 }
 
 
 
-pub fn insert_all<T: Copy> (src: &[T], dest: &mut Vec<T>, position: usize) {
-    for (i, v) in src.iter().enumerate() {
-        dest.insert(position + i, *v);
+pub fn join_lines (contents: &TextBuffer, line_ending: LineEnding) -> String {
+    let contents = contents.to_string();
+    match line_ending {
+        LineEnding::Lf => contents,
+        LineEnding::CrLf => contents.replace('\n', "\r\n"),
     }
 }
 
 
 
+// positions at the insertion point get pushed to the end of the inserted text
+pub fn get_pos_after_insertion (pos: (usize, usize), start: (usize, usize), end: (usize, usize)) -> (usize, usize) {
+    let (x, y) = pos;
+    if (y, x) < (start.1, start.0) {return pos;}
+    if y == start.1 {
+        (end.0 + x - start.0, end.1)
+    } else {
+        (x, y + end.1 - start.1)
+    }
+}
+
+// positions inside the removed text end up at its start
+pub fn get_pos_after_removal (pos: (usize, usize), start: (usize, usize), end: (usize, usize)) -> (usize, usize) {
+    let (x, y) = pos;
+    if (y, x) <= (start.1, start.0) {return pos;}
+    if (y, x) <= (end.1, end.0) {return start;}
+    if y == end.1 {
+        (start.0 + x - end.0, start.1)
+    } else {
+        (x, y - (end.1 - start.1))
+    }
+}
+
+
+
+pub fn get_text_end_pos (start: (usize, usize), text: &str) -> (usize, usize) {
+    let (start_x, start_y) = start;
+    match text.rfind('\n') {
+        Some(last_newline) => (text[last_newline + 1 ..].chars().count(), start_y + text.matches('\n').count()),
+        None => (start_x + text.chars().count(), start_y),
+    }
+}
+
+
+
+pub fn format_time_ago (time: SystemTime) -> String {
+    let seconds = time.elapsed().map_or(0, |elapsed| elapsed.as_secs());
+    match seconds {
+        0..=59 => format!("{seconds}s ago"),
+        60..=3599 => format!("{}m ago", seconds / 60),
+        3600..=86399 => format!("{}h ago", seconds / 3600),
+        _ => format!("{}d ago", seconds / 86400),
+    }
+}
+
+
+
+// grapheme clusters are what the user sees as a single character, like an emoji with modifiers or a letter with accents
+
+// returns each grapheme cluster along with its starting char index
+pub fn get_graphemes (line: &[char]) -> Vec<(usize, String)> {
+    let line = line.iter().collect::<String>();
+    let mut char_index = 0;
+    line.graphemes(true).map(|grapheme| {
+        let start = char_index;
+        char_index += grapheme.chars().count();
+        (start, grapheme.to_string())
+    }).collect()
+}
+
+// includes both the start and end of the line
+pub fn get_grapheme_boundaries (line: &[char]) -> Vec<usize> {
+    let mut boundaries = get_graphemes(line).into_iter().map(|(start, _)| start).collect::<Vec<usize>>();
+    boundaries.push(line.len());
+    boundaries
+}
+
+// wide characters (like most cjk characters) take up two columns, tabs go to the next tab stop, and nothing takes up less than one
+pub fn get_grapheme_width (grapheme: &str, column: usize, tab_width: usize) -> usize {
+    if grapheme == "\t" {
+        let tab_width = tab_width.max(1);
+        return tab_width - column % tab_width;
+    }
+    grapheme.width().max(1)
+}
+
+pub fn get_column (line: &[char], x: usize, tab_width: usize) -> usize {
+    let mut column = 0;
+    for (start, grapheme) in get_graphemes(line) {
+        if start >= x {break;}
+        column += get_grapheme_width(&grapheme, column, tab_width);
+    }
+    column
+}
+
+// returns the last grapheme boundary that's at or before the column
+pub fn get_x_at_column (line: &[char], column: usize, tab_width: usize) -> usize {
+    let mut current_column = 0;
+    for (start, grapheme) in get_graphemes(line) {
+        current_column += get_grapheme_width(&grapheme, current_column, tab_width);
+        if current_column > column {return start;}
+    }
+    line.len()
+}
+
+
+
+// returns the char index and column that each row starts at, rows are broken after whitespace when possible
+pub fn get_wrapped_rows (line: &[char], wrap_width: usize, tab_width: usize) -> Vec<(usize, usize)> {
+    let wrap_width = wrap_width.max(1);
+    let mut rows = vec!((0, 0));
+    let mut column = 0;
+    let mut last_break = None;
+    for (start, grapheme) in get_graphemes(line) {
+        let width = get_grapheme_width(&grapheme, column, tab_width);
+        let is_whitespace = grapheme.chars().all(char::is_whitespace);
+
+        // whitespace is allowed to hang off the end of a row
+        let row_start_column = rows.last().unwrap().1;
+        if !is_whitespace && column + width - row_start_column > wrap_width && column > row_start_column {
+            let new_row = last_break.take().unwrap_or((start, column));
+            rows.push(new_row);
+            // a word that's too long to fit on its own row gets split
+            if column + width - new_row.1 > wrap_width && column > new_row.1 {
+                rows.push((start, column));
+            }
+        }
+
+        column += width;
+        if is_whitespace {last_break = Some((start + grapheme.chars().count(), column));}
+    }
+    rows
+}
+
+// a position at the very end of a row is shown at the start of the next one
+pub fn get_wrapped_row_num (wrapped_rows: &[(usize, usize)], x: usize) -> usize {
+    wrapped_rows.iter().rposition(|(start_x, _)| *start_x <= x).unwrap_or(0)
+}
+
+pub fn get_wrap_width (program_data: &ProgramData) -> Option<usize> {
+    some_if(*program_data.soft_wrap.read(), || *program_data.wrap_width.read())
+}
+
+
+
+// the indentation and comment marker at the start of a line, which reflowing keeps on every line
+// the line comment can be repeated (like "///" or "##"), and "*" is only used when the block comments start with it (like "/*")
+pub fn get_line_prefix_len (line: &[char], language: &LanguageSettings) -> usize {
+    let is_space = |char: &&char| matches!(char, ' ' | '\t');
+    let indent_len = line.iter().take_while(is_space).count();
+    let line_comment = language.line_comment.iter().map(|line_comment| (line_comment.chars().collect::<Vec<char>>(), true));
+    let block_comment_middle = language.block_comment.iter().filter(|(start, _)| start.ends_with('*')).map(|_| (vec!('*'), false));
+    for (marker, can_repeat) in line_comment.chain(block_comment_middle) {
+        let mut marker_end = indent_len;
+        while line[marker_end..].starts_with(&marker) {
+            marker_end += marker.len();
+            if !can_repeat {break;}
+        }
+        if marker_end == indent_len {continue;}
+        if can_repeat {
+            marker_end += line[marker_end..].iter().take_while(|char| Some(*char) == marker.last()).count();
+        }
+        let space_len = line[marker_end..].iter().take_while(is_space).count();
+        // single chars like "#" and "*" need a space after them, so that things like "#include" and "*ptr" aren't prefixes
+        if marker.len() == 1 && space_len == 0 && marker_end < line.len() {continue;}
+        return marker_end + space_len;
+    }
+    indent_len
+}
+
+// list items like "* item", "- item", and "1. item" start a new paragraph, and their marker is kept on the first line only
+pub fn get_list_marker_len (text: &[char]) -> usize {
+    let marker_len = match text.first() {
+        Some('*' | '-' | '+') => 1,
+        Some(char) if char.is_ascii_digit() => {
+            let digit_len = text.iter().take_while(|char| char.is_ascii_digit()).count();
+            if !matches!(text.get(digit_len), Some('.' | ')')) {return 0;}
+            digit_len + 1
+        }
+        _ => return 0,
+    };
+    let space_len = text[marker_len..].iter().take_while(|char| matches!(char, ' ' | '\t')).count();
+    if space_len == 0 {return 0;}
+    marker_len + space_len
+}
+
+pub fn is_list_item (line: &[char], language: &LanguageSettings) -> bool {
+    get_list_marker_len(&line[get_line_prefix_len(line, language)..]) > 0
+}
+
+pub fn is_line_blank (line: &[char], language: &LanguageSettings) -> bool {
+    line[get_line_prefix_len(line, language)..].iter().all(|char| char.is_whitespace())
+}
+
+// every line gets the first line's prefix, and lines only go past the column when a single word doesn't fit
+pub fn reflow_lines (lines: &[Vec<char>], column: usize, tab_width: usize, language: &LanguageSettings) -> Vec<String> {
+    let prefix_len = get_line_prefix_len(&lines[0], language);
+    let list_marker_len = get_list_marker_len(&lines[0][prefix_len..]);
+    let first_prefix = &lines[0][..prefix_len + list_marker_len];
+    let first_prefix_width = get_column(first_prefix, first_prefix.len(), tab_width);
+    // the lines after a list item's first line are indented to line up with its text
+    let prefix = &lines[0][..prefix_len];
+    let prefix_width = get_column(prefix, prefix.len(), tab_width);
+    let prefix = prefix.iter().collect::<String>() + &" ".repeat(first_prefix_width - prefix_width);
+    let first_prefix = first_prefix.iter().collect::<String>();
+    let words = lines.iter().enumerate()
+        .flat_map(|(i, line)| {
+            let content_start = if i == 0 {prefix_len + list_marker_len} else {get_line_prefix_len(line, language)};
+            line[content_start..].iter().collect::<String>()
+                .split_whitespace()
+                .map(str::to_string)
+                .collect::<Vec<String>>()
+        });
+
+    let mut new_lines = vec!();
+    let mut current_line = String::new();
+    let mut current_width = first_prefix_width;
+    for word in words {
+        let word_width = word.width();
+        if !current_line.is_empty() && current_width + 1 + word_width > column {
+            let line_prefix = if new_lines.is_empty() {&first_prefix} else {&prefix};
+            new_lines.push(format!("{line_prefix}{current_line}"));
+            current_line.clear();
+            current_width = first_prefix_width;
+        }
+        if !current_line.is_empty() {
+            current_line.push(' ');
+            current_width += 1;
+        }
+        current_line += &word;
+        current_width += word_width;
+    }
+    let line_prefix = if new_lines.is_empty() {&first_prefix} else {&prefix};
+    new_lines.push(format!("{line_prefix}{current_line}"));
+    new_lines
+}
+
+
+
+// looks at how lines are indented compared to the line before them, returns None if nothing is indented
+pub fn detect_indentation (text: &str) -> Option<Indentation> {
+    let mut tab_lines = 0;
+    let mut space_lines = 0;
+    let mut space_indent_counts = [0; 9];
+    let mut last_space_indent = 0;
+    for line in text.lines().take(10000) {
+        if line.trim().is_empty() {continue;}
+        if line.starts_with('\t') {
+            tab_lines += 1;
+            continue;
+        }
+        let space_indent = line.len() - line.trim_start_matches(' ').len();
+        if space_indent > 0 {space_lines += 1;}
+        if space_indent > last_space_indent && space_indent - last_space_indent < space_indent_counts.len() {
+            space_indent_counts[space_indent - last_space_indent] += 1;
+        }
+        last_space_indent = space_indent;
+    }
+
+    if tab_lines == 0 && space_lines == 0 {return None;}
+    if tab_lines > space_lines {return Some(Indentation::Tabs);}
+    let most_common_indent = (1..space_indent_counts.len()).max_by_key(|indent| space_indent_counts[*indent])?;
+    if space_indent_counts[most_common_indent] == 0 {return None;}
+    Some(Indentation::Spaces(most_common_indent))
+}
+
+
+
+pub fn is_word_char (char: char) -> bool {
+    char.is_alphanumeric() || char == '_'
+}
+
+// returns the start and end of the word that x is in or right after
+pub fn get_word_range (line: &[char], x: usize) -> Option<(usize, usize)> {
+    let is_word_at = |x: usize| line.get(x).is_some_and(|char| is_word_char(*char));
+    let word_x = if is_word_at(x) {x} else if x > 0 && is_word_at(x - 1) {x - 1} else {return None;};
+    let start = (0..word_x).rev().take_while(|x| is_word_at(*x)).last().unwrap_or(word_x);
+    let end = (word_x..line.len()).take_while(|x| is_word_at(*x)).last().unwrap_or(word_x) + 1;
+    Some((start, end))
+}
+
+
+
+// word movement skips whitespace, then a run of word chars or a run of other symbols
+
+pub fn get_next_word_boundary (line: &[char], mut x: usize) -> usize {
+    while x < line.len() && line[x].is_whitespace() {x += 1;}
+    let Some(first_char) = line.get(x) else {return x;};
+    let is_word = is_word_char(*first_char);
+    while x < line.len() && !line[x].is_whitespace() && is_word_char(line[x]) == is_word {x += 1;}
+    x
+}
+
+pub fn get_prev_word_boundary (line: &[char], mut x: usize) -> usize {
+    while x > 0 && line[x - 1].is_whitespace() {x -= 1;}
+    if x == 0 {return 0;}
+    let is_word = is_word_char(line[x - 1]);
+    while x > 0 && !line[x - 1].is_whitespace() && is_word_char(line[x - 1]) == is_word {x -= 1;}
+    x
+}
+
+
+
+pub const BRACKET_PAIRS: [(char, char); 3] = [('(', ')'), ('[', ']'), ('{', '}')];
+pub const BRACKET_SEARCH_LINES: usize = 500; // brackets are only matched this many lines away, which also limits how much has to be scanned for strings and comments
+
+// the lines that brackets are matched in, which is built once and then used for every cursor
+pub struct BracketSearchArea {
+    pub first_y: usize,
+    pub lines: Vec<Vec<char>>,
+    pub code_mask: Vec<Vec<bool>>, // brackets in strings and comments are skipped when the language is known
+}
+
+impl BracketSearchArea {
+
+    // covers the lines from first_y to last_y, plus BRACKET_SEARCH_LINES on either side
+    pub fn new (contents: &TextBuffer, first_y: usize, last_y: usize, language: Option<&LanguageSettings>) -> Self {
+        let first_y = first_y.saturating_sub(BRACKET_SEARCH_LINES);
+        let last_y = (last_y + BRACKET_SEARCH_LINES).min(contents.len_lines() - 1);
+        let lines = (first_y..=last_y).map(|y| contents.line(y)).collect::<Vec<Vec<char>>>();
+        let code_mask = get_code_mask(&lines, language, first_y > 0);
+        Self {
+            first_y,
+            lines,
+            code_mask,
+        }
+    }
+
+    pub fn is_bracket_at (&self, x: usize, y: usize) -> bool {
+        let Some(line_y) = y.checked_sub(self.first_y) else {return false;};
+        self.lines.get(line_y).and_then(|line| line.get(x)).is_some_and(|char| self.code_mask[line_y][x] && get_bracket_pair(*char).is_some())
+    }
+
+    // returns the position of the bracket next to the cursor and the position of its match
+    pub fn find_matching_bracket (&self, x: usize, y: usize) -> Option<((usize, usize), (usize, usize))> {
+        let bracket_x = [Some(x), x.checked_sub(1)].into_iter().flatten().find(|x| self.is_bracket_at(*x, y))?;
+        let (match_x, match_y) = self.find_bracket_match((bracket_x, y - self.first_y))?;
+        Some(((bracket_x, y), (match_x, match_y + self.first_y)))
+    }
+
+    // returns the opening and closing brackets next to the cursor, or else the closest ones around it
+    pub fn find_enclosing_brackets (&self, x: usize, y: usize) -> Option<((usize, usize), (usize, usize))> {
+        if let Some((bracket_pos, match_pos)) = self.find_matching_bracket(x, y) {
+            let is_opener = BRACKET_PAIRS.iter().any(|(opener, _)| *opener == self.lines[bracket_pos.1 - self.first_y][bracket_pos.0]);
+            return Some(if is_opener {(bracket_pos, match_pos)} else {(match_pos, bracket_pos)});
+        }
+        let line_y = y.checked_sub(self.first_y).filter(|line_y| *line_y < self.lines.len())?;
+        // this goes backwards until an opener that isn't closed before the cursor
+        let mut depths = [0; BRACKET_PAIRS.len()];
+        for (bracket_x, bracket_y) in get_positions_from(&self.lines, (x, line_y), false) {
+            if !self.code_mask[bracket_y][bracket_x] {continue;}
+            let char = self.lines[bracket_y][bracket_x];
+            let Some(pair_num) = BRACKET_PAIRS.iter().position(|(opener, closer)| *opener == char || *closer == char) else {continue;};
+            if char == BRACKET_PAIRS[pair_num].1 {
+                depths[pair_num] += 1;
+            } else if depths[pair_num] > 0 {
+                depths[pair_num] -= 1;
+            } else {
+                let (match_x, match_y) = self.find_bracket_match((bracket_x, bracket_y))?;
+                return Some(((bracket_x, bracket_y + self.first_y), (match_x, match_y + self.first_y)));
+            }
+        }
+        None
+    }
+
+    // openers are matched forwards and closers backwards, and only brackets of the same kind are counted
+    fn find_bracket_match (&self, pos: (usize, usize)) -> Option<(usize, usize)> {
+        let bracket = self.lines[pos.1][pos.0];
+        let (opener, closer) = get_bracket_pair(bracket)?;
+        let forwards = bracket == opener;
+        let start_pos = if forwards {pos} else {(pos.0 + 1, pos.1)};
+        let mut depth = 0;
+        for (x, y) in get_positions_from(&self.lines, start_pos, forwards) {
+            if !self.code_mask[y][x] {continue;}
+            let char = self.lines[y][x];
+            if char == bracket {depth += 1;}
+            if char == if forwards {closer} else {opener} {depth -= 1;}
+            if depth == 0 {return Some((x, y));}
+        }
+        None
+    }
+
+}
+
+pub fn get_bracket_pair (char: char) -> Option<(char, char)> {
+    BRACKET_PAIRS.iter().find(|(opener, closer)| *opener == char || *closer == char).copied()
+}
+
+// forwards includes the starting position, backwards starts right before it
+pub fn get_positions_from (lines: &[Vec<char>], (x, y): (usize, usize), forwards: bool) -> Box<dyn Iterator<Item = (usize, usize)> + '_> {
+    if forwards {
+        Box::new((y..lines.len()).flat_map(move |line_y| {
+            let start_x = if line_y == y {x} else {0};
+            (start_x..lines[line_y].len()).map(move |x| (x, line_y))
+        }))
+    } else {
+        Box::new((0..=y).rev().flat_map(move |line_y| {
+            let end_x = if line_y == y {x.min(lines[line_y].len())} else {lines[line_y].len()};
+            (0..end_x).rev().map(move |x| (x, line_y))
+        }))
+    }
+}
+
+// marks which chars are code instead of being in a string, char literal or comment
+// strings end at the end of the line, so a quote that isn't closed can't throw off the rest of the file
+// when the lines don't start at the top of the file, a block comment end before any block comment start means they started inside that comment
+pub fn get_code_mask (lines: &[Vec<char>], language: Option<&LanguageSettings>, can_start_in_comment: bool) -> Vec<Vec<bool>> {
+    let Some(language) = language else {
+        return lines.iter().map(|line| vec![true; line.len()]).collect();
+    };
+    let is_token_at = |line: &[char], x: usize, token: &str| {
+        let token = token.chars().collect::<Vec<char>>();
+        line[x..].starts_with(&token)
+    };
+
+    let mut code_mask = lines.iter().map(|line| vec![false; line.len()]).collect::<Vec<Vec<bool>>>();
+    let mut is_in_block_comment = false;
+    let mut has_found_block_comment = !can_start_in_comment;
+    for (y, line) in lines.iter().enumerate() {
+        let mut string_quote = None;
+        let mut x = 0;
+        while x < line.len() {
+            if let Some(quote) = string_quote {
+                if line[x] == '\\' {x += 1;}
+                else if line[x] == quote {string_quote = None;}
+                x += 1;
+                continue;
+            }
+            if is_in_block_comment {
+                match &language.block_comment {
+                    Some((_, comment_end)) if is_token_at(line, x, comment_end) => {
+                        is_in_block_comment = false;
+                        x += comment_end.chars().count();
+                    }
+                    _ => x += 1,
+                }
+                continue;
+            }
+            if let Some((comment_start, comment_end)) = &language.block_comment {
+                if is_token_at(line, x, comment_start) {
+                    is_in_block_comment = true;
+                    has_found_block_comment = true;
+                    x += comment_start.chars().count();
+                    continue;
+                }
+                if !has_found_block_comment && is_token_at(line, x, comment_end) {
+                    code_mask[..=y].iter_mut().for_each(|line_mask| line_mask.fill(false));
+                    has_found_block_comment = true;
+                    x += comment_end.chars().count();
+                    continue;
+                }
+            }
+            if language.line_comment.as_ref().is_some_and(|line_comment| is_token_at(line, x, line_comment)) {break;}
+            if language.char_literal == Some(line[x]) {
+                if let Some(char_literal_len) = get_char_literal_len(&line[x..]) {
+                    x += char_literal_len;
+                    continue;
+                }
+            }
+            if language.string_delimiters.contains(&line[x]) {
+                string_quote = Some(line[x]);
+            } else {
+                code_mask[y][x] = true;
+            }
+            x += 1;
+        }
+    }
+    code_mask
+}
+
+// char literals are either one char ('x') or an escape ('\n', '\''), and anything else (like a lifetime) isn't one
+pub fn get_char_literal_len (text: &[char]) -> Option<usize> {
+    let quote = *text.first()?;
+    match text.get(1..)? {
+        ['\\', _, rest @ ..] => rest.iter().position(|char| *char == quote).map(|quote_x| quote_x + 4),
+        [_, second, ..] if *second == quote => Some(3),
+        _ => None,
+    }
+}
+
+
+
+// numbers are compared by their value, so "file2" comes before "file10"
+pub fn natural_cmp (a: &str, b: &str) -> Ordering {
+    let (mut a_chars, mut b_chars) = (a.chars().peekable(), b.chars().peekable());
+    loop {
+        let ordering = match (a_chars.peek().copied(), b_chars.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a_char), Some(b_char)) if a_char.is_ascii_digit() && b_char.is_ascii_digit() => {
+                let (a_number, b_number) = (take_number(&mut a_chars), take_number(&mut b_chars));
+                a_number.len().cmp(&b_number.len()).then_with(|| a_number.cmp(&b_number))
+            }
+            (Some(a_char), Some(b_char)) => {
+                a_chars.next();
+                b_chars.next();
+                a_char.to_lowercase().cmp(b_char.to_lowercase())
+            }
+        };
+        if ordering != Ordering::Equal {return ordering;}
+    }
+}
+
+// leading zeros are skipped so that the lengths can be compared
+fn take_number (chars: &mut Peekable<Chars>) -> String {
+    let mut number = String::new();
+    while let Some(char) = chars.next_if(char::is_ascii_digit) {
+        if number.is_empty() && char == '0' {continue;}
+        number.push(char);
+    }
+    number
+}
+
+
+
 pub fn some_if<T> (condition: bool, some_fn: impl FnOnce() -> T) -> Option<T> {
     if condition {
         Some(some_fn())
@@ -197,3 +714,153 @@ pub fn get_current_file_mut<'a> (program_data: &ProgramData, files: &'a mut RwLo
     Ok(Some(&mut files[current_file_num]))
 
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_lines (text: &str) -> Vec<Vec<char>> {
+        text.split('\n').map(|line| line.chars().collect()).collect()
+    }
+
+    fn reflow (text: &str, column: usize, file_path: &str) -> String {
+        let settings = ProgramSettings::default();
+        reflow_lines(&to_lines(text), column, 4, settings.get_language(file_path)).join("\n")
+    }
+
+    #[test]
+    fn reflow_keeps_line_comments () {
+        assert_eq!(reflow("    // one two three\n    // four five", 20, "a.rs"), "    // one two three\n    // four five");
+        assert_eq!(reflow("/// one two three four five", 16, "a.rs"), "/// one two\n/// three four\n/// five");
+        assert_eq!(reflow("# one two\n# three", 80, "a.py"), "# one two three");
+        assert_eq!(reflow(" * one two\n * three", 80, "a.c"), " * one two three");
+    }
+
+    #[test]
+    fn reflow_only_uses_the_language_comments () {
+        assert_eq!(reflow("# title words", 8, "a.md"), "# title\nwords");
+        assert_eq!(reflow("// a b", 80, "a.py"), "// a b");
+        assert_eq!(get_line_prefix_len(&to_lines("#include <a>")[0], ProgramSettings::default().get_language("a.c")), 0);
+    }
+
+    #[test]
+    fn reflow_list_items () {
+        assert_eq!(reflow("* one two three four", 12, "a.md"), "* one two\n  three four");
+        assert_eq!(reflow("// 12. one two three four", 16, "a.rs"), "// 12. one two\n//     three\n//     four");
+        let settings = ProgramSettings::default();
+        let language = settings.get_language("a.md");
+        assert!(is_list_item(&to_lines("  - item")[0], language));
+        assert!(!is_list_item(&to_lines("-item")[0], language));
+        assert!(!is_list_item(&to_lines("1 item")[0], language));
+    }
+
+    #[test]
+    fn natural_cmp_compares_numbers_by_value () {
+        let mut lines = vec!("file10", "File2", "file1", "file02b", "file", "a10b2", "a10b10");
+        lines.sort_by(|a, b| natural_cmp(a, b));
+        assert_eq!(lines, vec!("a10b2", "a10b10", "file", "file1", "File2", "file02b", "file10"));
+        assert_eq!(natural_cmp("x007", "x7"), "x007".cmp("x7"));
+    }
+
+    #[test]
+    fn positions_after_insertion () {
+        // "ab|c" with "x\nyz" inserted at 1
+        let (start, end) = ((1, 0), (2, 1));
+        assert_eq!(get_pos_after_insertion((0, 0), start, end), (0, 0));
+        assert_eq!(get_pos_after_insertion((1, 0), start, end), (2, 1));
+        assert_eq!(get_pos_after_insertion((3, 0), start, end), (4, 1));
+        assert_eq!(get_pos_after_insertion((3, 2), start, end), (3, 3));
+    }
+
+    #[test]
+    fn positions_after_removal () {
+        // the text from (1, 0) to (2, 1) is removed
+        let (start, end) = ((1, 0), (2, 1));
+        assert_eq!(get_pos_after_removal((1, 0), start, end), (1, 0));
+        assert_eq!(get_pos_after_removal((5, 0), start, end), (1, 0));
+        assert_eq!(get_pos_after_removal((2, 1), start, end), (1, 0));
+        assert_eq!(get_pos_after_removal((4, 1), start, end), (3, 0));
+        assert_eq!(get_pos_after_removal((4, 3), start, end), (4, 2));
+    }
+
+    #[test]
+    fn insertion_and_removal_round_trip () {
+        let (start, end) = ((3, 2), (1, 4));
+        for pos in [(0, 0), (2, 2), (4, 2), (0, 3), (5, 5)] {
+            let inserted_pos = get_pos_after_insertion(pos, start, end);
+            assert_eq!(get_pos_after_removal(inserted_pos, start, end), pos);
+        }
+    }
+
+    fn find_rust_bracket (text: &str, x: usize, y: usize) -> Option<((usize, usize), (usize, usize))> {
+        let settings = ProgramSettings::default();
+        let contents = TextBuffer::from_text(text);
+        BracketSearchArea::new(&contents, y, y, settings.get_known_language("a.rs")).find_matching_bracket(x, y)
+    }
+
+    #[test]
+    fn brackets_in_char_literals_are_skipped () {
+        assert_eq!(find_rust_bracket("f('{', x)", 1, 0), Some(((1, 0), (8, 0))));
+        assert_eq!(find_rust_bracket("f('\"', x)", 1, 0), Some(((1, 0), (8, 0))));
+        assert_eq!(find_rust_bracket("f('\\'', x)", 1, 0), Some(((1, 0), (9, 0))));
+        assert_eq!(find_rust_bracket("f('\\\\', x)", 1, 0), Some(((1, 0), (9, 0))));
+        assert_eq!(find_rust_bracket("fn f<'a>(x: &'a str) {}", 21, 0), Some(((21, 0), (22, 0))));
+    }
+
+    #[test]
+    fn brackets_in_strings_are_skipped () {
+        assert_eq!(find_rust_bracket("f(\"(\\\")\", x)", 1, 0), Some(((1, 0), (11, 0))));
+        assert_eq!(find_rust_bracket("f(\n\")\"\n)", 1, 0), Some(((1, 0), (0, 2))));
+        // a string that isn't closed ends with its line
+        assert_eq!(find_rust_bracket("f(\"\n)", 1, 0), Some(((1, 0), (0, 1))));
+    }
+
+    #[test]
+    fn brackets_in_comments_are_skipped () {
+        assert_eq!(find_rust_bracket("f( // )\n)", 1, 0), Some(((1, 0), (0, 1))));
+        assert_eq!(find_rust_bracket("f( /* ) */ )", 1, 0), Some(((1, 0), (11, 0))));
+    }
+
+    #[test]
+    fn search_area_can_start_inside_a_block_comment () {
+        let settings = ProgramSettings::default();
+        let language = settings.get_known_language("a.rs");
+        let lines = to_lines("( */ (\n)");
+        assert_eq!(get_code_mask(&lines, language, true), vec!(vec!(false, false, false, false, true, true), vec!(true)));
+        assert_eq!(get_code_mask(&lines, language, false), vec!(vec!(true; 6), vec!(true)));
+    }
+
+    #[test]
+    fn enclosing_brackets () {
+        let settings = ProgramSettings::default();
+        let contents = TextBuffer::from_text("a(b[c], {d})");
+        let search_area = BracketSearchArea::new(&contents, 0, 0, settings.get_known_language("a.rs"));
+        assert_eq!(search_area.find_enclosing_brackets(10, 0), Some(((8, 0), (10, 0))));
+        assert_eq!(search_area.find_enclosing_brackets(7, 0), Some(((1, 0), (11, 0))));
+        assert_eq!(search_area.find_enclosing_brackets(0, 0), None);
+    }
+
+    #[test]
+    fn indentation_is_detected_from_indent_changes () {
+        assert_eq!(detect_indentation("a\n  b\n    c\n  d\n"), Some(Indentation::Spaces(2)));
+        assert_eq!(detect_indentation("a\n    b\n        c\n"), Some(Indentation::Spaces(4)));
+        assert_eq!(detect_indentation("a\n\tb\n\t\tc\n  d\n"), Some(Indentation::Tabs));
+        assert_eq!(detect_indentation("a\nb\n\n"), None);
+    }
+
+    #[test]
+    fn wrapped_rows_break_after_whitespace () {
+        let wrap = |text: &str, wrap_width: usize| get_wrapped_rows(&text.chars().collect::<Vec<char>>(), wrap_width, 4);
+        assert_eq!(wrap("aaa bbb ccc", 8), vec!((0, 0), (8, 8)));
+        assert_eq!(wrap("aaa bbb", 80), vec!((0, 0)));
+        // whitespace can hang off the end of a row
+        assert_eq!(wrap("aaa    ", 4), vec!((0, 0)));
+        // words that are too long are split
+        assert_eq!(wrap("abcdefghij", 4), vec!((0, 0), (4, 4), (8, 8)));
+        assert_eq!(get_wrapped_row_num(&[(0, 0), (8, 8)], 8), 1);
+        assert_eq!(get_wrapped_row_num(&[(0, 0), (8, 8)], 7), 0);
+    }
+
+}
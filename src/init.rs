@@ -5,6 +5,7 @@ use sdl2::{Sdl, pixels::Color,
     video::{Window, WindowContext},
     ttf::{Sdl2TtfContext, Font}
 };
+use std::collections::HashMap;
 
 
 
@@ -50,6 +51,7 @@ pub fn init_program_data<'a> (program_data: &ProgramData, texture_creator: &'a T
     let font = ttf_context.load_font(font_path, settings.font_size as u16)?;
 
     let textures = load_textures(&font, texture_creator)?;
+    *program_data.soft_wrap.write() = settings.soft_wrap;
 
     drop(settings_mutex);
     continue_session(program_data)?;
@@ -71,6 +73,7 @@ pub fn load_textures<'a> (font: &Font, texture_creator: &'a TextureCreator<Windo
 
     Ok(ProgramTextures {
         ascii_chars,
+        other_graphemes: HashMap::new(),
     })
 }
 
@@ -80,8 +83,12 @@ pub fn render_char<'a> (char: char, font: &Font, texture_creator: &'a TextureCre
     if char as u32 == 0 {
         return fns::get_empty_texture(texture_creator);
     }
+    render_grapheme(&char.to_string(), font, texture_creator)
+}
+
+pub fn render_grapheme<'a> (grapheme: &str, font: &Font, texture_creator: &'a TextureCreator<WindowContext>) -> Result<Texture<'a>, ProgramError> {
     let char_surface = match font
-        .render(&char.to_string())
+        .render(grapheme)
         .blended(Color::RGB(255, 255, 255))
     {
         stdResult::Ok(v) => v,
@@ -1,9 +1,9 @@
 pub use crate::{*,
     update_mod::update,
-    background_tasks_mod::background_tasks,
+    background_tasks_mod::{background_tasks, undo_journal, file_encoding},
     update_mod::events,
     additions::*,
-    data_mod::{program_data::*, settings::*, errors::*},
+    data_mod::{program_data::*, settings::*, errors::*, text_buffer::*, lazy_text::*, edit_history::*},
 };
 
 pub use std::{fmt, fs,
@@ -6,7 +6,7 @@ use sdl2::{video::WindowContext, ttf::Font, pixels::Color,
 
 
 
-pub fn render(canvas: &mut WindowCanvas, program_data: &ProgramData, textures: &mut ProgramTextures<'_>, texture_creator: &TextureCreator<WindowContext>, font: &Font) -> Result<(), ProgramError> {
+pub fn render<'a> (canvas: &mut WindowCanvas, program_data: &ProgramData, textures: &mut ProgramTextures<'a>, texture_creator: &'a TextureCreator<WindowContext>, font: &Font) -> Result<(), ProgramError> {
 
     // render (and resume tasks)
     prepare_canvas(canvas, program_data, textures, texture_creator, font)?;
@@ -32,7 +32,7 @@ pub fn render(canvas: &mut WindowCanvas, program_data: &ProgramData, textures: &
 
 
 
-pub fn prepare_canvas (canvas: &mut WindowCanvas, program_data: &ProgramData, textures: &mut ProgramTextures<'_>, texture_creator: &TextureCreator<WindowContext>, font: &Font) -> Result<(), ProgramError> {
+pub fn prepare_canvas<'a> (canvas: &mut WindowCanvas, program_data: &ProgramData, textures: &mut ProgramTextures<'a>, texture_creator: &'a TextureCreator<WindowContext>, font: &Font) -> Result<(), ProgramError> {
 
     // get data
     let settings_mutex = program_data.settings.read();
@@ -49,30 +49,61 @@ pub fn prepare_canvas (canvas: &mut WindowCanvas, program_data: &ProgramData, te
     canvas.set_draw_color(fns::blend_colors(settings.background_color, Color::RGB(0, 0, 0), 0.5));
     canvas.draw_line(Point::new(0, buttons_bottom_y as i32), Point::new(width as i32, buttons_bottom_y as i32))?;
 
-
-    // render text
-    let files = program_data.files.write();
-    let current_file = match fns::get_current_file(program_data, &files)? {
+    let mut files = program_data.files.write();
+    let current_file = match fns::get_current_file_mut(program_data, &mut files)? {
         Some(v) => v,
         None => return Ok(()),
     };
 
+
+    // render indexing progress
+    if let Some(indexing_progress) = current_file.contents.get_indexing_progress() {
+        let bar_height = (buttons_bottom_y / 4).max(1);
+        let bar_rect = Rect::new(0, (buttons_bottom_y - bar_height) as i32, (width as f64 * indexing_progress) as u32, bar_height);
+        canvas.set_draw_color(settings.cursor_color);
+        canvas.fill_rect(bar_rect)?;
+    }
+
+
+    // render text
+
     let text_section = Rect::new(0, buttons_bottom_y as i32, width, height - buttons_bottom_y);
+    *program_data.text_section.write() = Some(text_section);
+    let render_cursor_lines = get_render_cursor_lines(program_data, settings);
+    let cursor_width = (width as f64 * settings.cursor_width) as u32;
+    let cursor_height = (settings.font_size as f64 * settings.cursor_height) as u32;
+    if let Some(hex_view) = &mut current_file.hex_view {
+        return render_hex_view(hex_view, cursor_width, cursor_height, render_cursor_lines, &text_section, font, canvas, texture_creator, textures, settings);
+    }
     //let text_spacing = (settings.font_size as f64 * settings.font_spacing) as u32;
-    for (i, current_line) in current_file.contents.iter().enumerate() {
-        render_text_line(current_line, i, &text_section, font, canvas, texture_creator, textures, settings)?;
+    *program_data.wrap_width.write() = get_visible_column_count(&text_section, settings).max(1);
+    let wrap_width = fns::get_wrap_width(program_data);
+    if wrap_width.is_some() {current_file.scroll_x = 0.;}
+    if current_file.scroll_to_cursor {
+        scroll_to_last_cursor(current_file, wrap_width, &text_section, settings);
+        current_file.scroll_to_cursor = false;
+    }
+    // only the visible lines are read, so lazily loaded files only decode what's on screen
+    let screen_rows = get_screen_rows(current_file, current_file.scroll_y as usize, get_visible_line_count(&text_section, settings) + 1, wrap_width);
+    let scroll_column = current_file.scroll_x as usize;
+    for (i, row) in screen_rows.iter().enumerate() {
+        render_text_line(&row.line[..row.end_x], i, row.start_column + scroll_column, current_file.tab_width, &text_section, font, canvas, texture_creator, textures, settings)?;
     }
 
 
+    // render bracket highlights
+    render_bracket_highlights(cursor_height, &screen_rows, current_file, canvas, &text_section, settings)?;
+
+
     // render cursors
-    let cursor_place_instant = program_data.cursor_place_instant.read();
-    let time_since_cursor_place = cursor_place_instant.elapsed().as_secs_f64();
-    let cursor_flashing_speed = settings.cursor_flashing_speed;
-    let render_cursor_lines = time_since_cursor_place % cursor_flashing_speed < cursor_flashing_speed / 2.;
-    let cursor_width = (width as f64 * settings.cursor_width) as u32;
-    let cursor_height = (settings.font_size as f64 * settings.cursor_height) as u32;
     for cursor in &current_file.cursors {
-        render_cursor(cursor, cursor_width, cursor_height, render_cursor_lines, current_file, canvas, &text_section, settings)?;
+        render_cursor(cursor, cursor_width, cursor_height, render_cursor_lines, &screen_rows, current_file, canvas, &text_section, settings)?;
+    }
+
+
+    // render history panel
+    if *program_data.show_history_panel.read() {
+        render_history_panel(&current_file.history, &text_section, font, canvas, texture_creator, textures, settings)?;
     }
 
     Ok(())
@@ -81,54 +112,152 @@ pub fn prepare_canvas (canvas: &mut WindowCanvas, program_data: &ProgramData, te
 
 
 
-pub fn render_text_line (text: &[char], text_y: usize, section: &Rect, font: &Font, canvas: &mut WindowCanvas, texture_creator: &TextureCreator<WindowContext>, textures: &ProgramTextures, settings: &ProgramSettings) -> Result<(), ProgramError> {
-    for (i, char) in text.iter().enumerate() {
-        let char = *char as usize;
-        if char < 256 {
-            let char_texture = &textures.ascii_chars[char];
-            let (x, y) = get_char_position(i, text_y, section, settings);
-            let (width, height) = fns::get_texture_size(char_texture);
-            let (src, dest) = clamp_to_section(&Rect::new(x, y, width, height), section);
-            canvas.copy(char_texture, Some(src), dest)?;
-        } else {
-            return err(RawProgramError::WIP {
-                details: format!("cannot render charater {char}")
+pub fn get_render_cursor_lines (program_data: &ProgramData, settings: &ProgramSettings) -> bool {
+    let time_since_cursor_place = program_data.cursor_place_instant.read().elapsed().as_secs_f64();
+    let cursor_flashing_speed = settings.cursor_flashing_speed;
+    time_since_cursor_place % cursor_flashing_speed < cursor_flashing_speed / 2.
+}
+
+
+
+// changes the scroll as little as possible to get the last cursor on screen
+pub fn scroll_to_last_cursor (current_file: &mut File, wrap_width: Option<usize>, section: &Rect, settings: &ProgramSettings) {
+    let Some(cursor) = current_file.cursors.last() else {return;};
+    let visible_lines = get_visible_line_count(section, settings).max(1);
+    let visible_columns = get_visible_column_count(section, settings).max(1);
+    let (scroll_x, scroll_y) = (current_file.scroll_x as usize, current_file.scroll_y as usize);
+    let tab_width = current_file.tab_width;
+    if cursor.y < scroll_y {
+        current_file.scroll_y = cursor.y as f64;
+        return;
+    }
+
+    let Some(wrap_width) = wrap_width else {
+        let cursor_column = current_file.contents.get_column(cursor.x, cursor.y, tab_width);
+        if cursor.y >= scroll_y + visible_lines {current_file.scroll_y = (cursor.y + 1 - visible_lines) as f64;}
+        if cursor_column < scroll_x {current_file.scroll_x = cursor_column as f64;}
+        if cursor_column >= scroll_x + visible_columns {current_file.scroll_x = (cursor_column + 1 - visible_columns) as f64;}
+        return;
+    };
+
+    // with soft wrap, lines can take up multiple rows, so this counts rows upwards from the cursor until the screen is full
+    let cursor_line = current_file.contents.line(cursor.y);
+    let mut rows_above = fns::get_wrapped_row_num(&fns::get_wrapped_rows(&cursor_line, wrap_width, tab_width), cursor.x) + 1;
+    let mut new_scroll_y = cursor.y;
+    while new_scroll_y > scroll_y {
+        let row_count = fns::get_wrapped_rows(&current_file.contents.line(new_scroll_y - 1), wrap_width, tab_width).len();
+        if rows_above + row_count > visible_lines {break;}
+        rows_above += row_count;
+        new_scroll_y -= 1;
+    }
+    current_file.scroll_y = new_scroll_y as f64;
+}
+
+
+
+// one row of text on the screen, which is a whole line unless soft wrap splits it up
+#[derive(Debug)]
+pub struct ScreenRow {
+    pub y: usize,
+    pub line: Vec<char>,
+    pub start_x: usize,
+    pub end_x: usize,
+    pub start_column: usize,
+    pub is_line_end: bool,
+}
+
+pub fn get_screen_rows (current_file: &File, first_line: usize, row_count: usize, wrap_width: Option<usize>) -> Vec<ScreenRow> {
+    let mut screen_rows = vec!();
+    let mut y = first_line;
+    while screen_rows.len() < row_count && y < current_file.contents.len_lines() {
+        let line = current_file.contents.line(y);
+        let wrapped_rows = match wrap_width {
+            Some(wrap_width) => fns::get_wrapped_rows(&line, wrap_width, current_file.tab_width),
+            None => vec!((0, 0)),
+        };
+        for (i, (start_x, start_column)) in wrapped_rows.iter().enumerate() {
+            let next_row_start = wrapped_rows.get(i + 1).map(|(next_start_x, _)| *next_start_x);
+            screen_rows.push(ScreenRow {
+                y,
+                line: line.clone(),
+                start_x: *start_x,
+                end_x: next_row_start.unwrap_or(line.len()),
+                start_column: *start_column,
+                is_line_end: next_row_start.is_none(),
             });
         }
+        y += 1;
+    }
+    screen_rows.truncate(row_count);
+    screen_rows
+}
+
+
+
+// the text is laid out by grapheme clusters, and anything before first_column is scrolled off the screen
+pub fn render_text_line<'a> (text: &[char], text_y: usize, first_column: usize, tab_width: usize, section: &Rect, font: &Font, canvas: &mut WindowCanvas, texture_creator: &'a TextureCreator<WindowContext>, textures: &mut ProgramTextures<'a>, settings: &ProgramSettings) -> Result<(), ProgramError> {
+    let mut column = 0;
+    for (_, grapheme) in fns::get_graphemes(text) {
+        let grapheme_column = column;
+        column += fns::get_grapheme_width(&grapheme, grapheme_column, tab_width);
+        if grapheme_column < first_column || grapheme == "\t" {continue;}
+        let mut chars = grapheme.chars();
+        let grapheme_texture = match (chars.next(), chars.next()) {
+            (Some(char), None) if (char as u32) < 256 => &textures.ascii_chars[char as usize],
+            _ => {
+                if !textures.other_graphemes.contains_key(&grapheme) {
+                    let new_texture = init::render_grapheme(&grapheme, font, texture_creator)?;
+                    textures.other_graphemes.insert(grapheme.clone(), new_texture);
+                }
+                &textures.other_graphemes[&grapheme]
+            }
+        };
+        let (x, y) = get_char_position(grapheme_column - first_column, text_y, section, settings);
+        let (width, height) = fns::get_texture_size(grapheme_texture);
+        let (src, dest) = clamp_to_section(&Rect::new(x, y, width, height), section);
+        canvas.copy(grapheme_texture, Some(src), dest)?;
     }
     Ok(())
 }
 
 
 
-pub fn render_cursor (cursor: &Cursor, cursor_width: u32, cursor_height: u32, render_cursor_lines: bool, current_file: &File, canvas: &mut WindowCanvas, section: &Rect, settings: &ProgramSettings) -> Result<(), ProgramError> {
+pub fn render_cursor (cursor: &Cursor, cursor_width: u32, cursor_height: u32, render_cursor_lines: bool, screen_rows: &[ScreenRow], current_file: &File, canvas: &mut WindowCanvas, section: &Rect, settings: &ProgramSettings) -> Result<(), ProgramError> {
+    let scroll_column = current_file.scroll_x as usize;
+    let tab_width = current_file.tab_width;
+    let get_screen_column = |row: &ScreenRow, x: usize| fns::get_column(&row.line, x, tab_width).saturating_sub(row.start_column + scroll_column);
 
-    // render selection
-    if let Some((mut selection_start_x, mut selection_start_y)) = cursor.selection_start {
-        let (mut selection_end_x, mut selection_end_y) = (cursor.x, cursor.y);
+    // render selection, positions are compared as (y, x) so that they're in order
+    if let Some((selection_x, selection_y)) = cursor.selection_start {
+        let (selection_start, selection_end) = if (selection_y, selection_x) < (cursor.y, cursor.x) {
+            ((selection_y, selection_x), (cursor.y, cursor.x))
+        } else {
+            ((cursor.y, cursor.x), (selection_y, selection_x))
+        };
         canvas.set_blend_mode(BlendMode::Blend);
         canvas.set_draw_color(settings.cursor_selection_color);
-        if selection_start_y == cursor.y {
-            if selection_start_x > selection_end_x {(selection_start_x, selection_end_x) = (selection_end_x, selection_start_x);}
-            render_rect_over_chars(selection_start_x, selection_end_x, selection_end_y, cursor_height, canvas, section, settings)?;
-        } else {
-            if selection_start_y > selection_end_y {(selection_start_x, selection_start_y, selection_end_x, selection_end_y) = (selection_end_x, selection_end_y, selection_start_x, selection_start_y);}
-            let contents = &current_file.contents;
-            render_rect_over_chars(selection_start_x, contents[selection_start_y].len() + 1, selection_start_y, cursor_height, canvas, section, settings)?;
-            for (i, current_line) in contents.iter().enumerate().take(selection_end_y).skip(selection_start_y + 1) {
-                render_rect_over_chars(0, current_line.len() + 1, i, cursor_height, canvas, section, settings)?;
-            }
-            render_rect_over_chars(0, selection_end_x, selection_end_y, cursor_height, canvas, section, settings)?;
+        for (i, row) in screen_rows.iter().enumerate() {
+            let (row_start, row_end) = ((row.y, row.start_x), (row.y, row.end_x));
+            if selection_end < row_start || selection_start > row_end {continue;}
+            let start_column = get_screen_column(row, selection_start.max(row_start).1);
+            let mut end_column = get_screen_column(row, selection_end.min(row_end).1);
+            if row.is_line_end && selection_end > row_end {end_column += 1;} // the line break is selected too
+            if start_column == end_column {continue;}
+            render_rect_over_chars(start_column, end_column, i, cursor_height, canvas, section, settings)?;
         }
         canvas.set_blend_mode(BlendMode::None);
     }
 
     if !render_cursor_lines {return Ok(());}
+    let cursor_row = screen_rows.iter().position(|row| row.y == cursor.y && row.start_x <= cursor.x && (cursor.x < row.end_x || row.is_line_end));
+    let Some(cursor_row) = cursor_row else {return Ok(());};
+    let row = &screen_rows[cursor_row];
+    if fns::get_column(&row.line, cursor.x, tab_width) < row.start_column + scroll_column {return Ok(());}
 
     // render cursor line
     let y_offset = (settings.font_size * 3 / 32) as i32;
     canvas.set_draw_color(settings.cursor_color);
-    let (cursor_x, cursor_y) = get_char_position(cursor.x, cursor.y, section, settings);
+    let (cursor_x, cursor_y) = get_char_position(get_screen_column(row, cursor.x), cursor_row, section, settings);
     let cursor_rect = Rect::new(cursor_x, cursor_y + y_offset, cursor_width, cursor_height);
     canvas.fill_rect(clamp_to_section(&cursor_rect, section).1)?;
 
@@ -137,6 +266,116 @@ pub fn render_cursor (cursor: &Cursor, cursor_width: u32, cursor_height: u32, re
 
 
 
+// the lines around the screen are only scanned for strings and comments once per frame, and only when a cursor on screen is next to a bracket
+pub fn render_bracket_highlights (cursor_height: u32, screen_rows: &[ScreenRow], current_file: &File, canvas: &mut WindowCanvas, section: &Rect, settings: &ProgramSettings) -> Result<(), ProgramError> {
+    let (Some(first_row), Some(last_row)) = (screen_rows.first(), screen_rows.last()) else {return Ok(());};
+    let is_next_to_bracket = |cursor: &&Cursor| [Some(cursor.x), cursor.x.checked_sub(1)].into_iter().flatten()
+        .any(|x| current_file.contents.char_at(x, cursor.y).is_some_and(|char| fns::get_bracket_pair(char).is_some()));
+    let visible_cursors = current_file.cursors.iter()
+        .filter(|cursor| cursor.y >= first_row.y && cursor.y <= last_row.y)
+        .filter(is_next_to_bracket)
+        .collect::<Vec<&Cursor>>();
+    if visible_cursors.is_empty() {return Ok(());}
+    let scroll_column = current_file.scroll_x as usize;
+    let tab_width = current_file.tab_width;
+    let language = settings.get_known_language(&current_file.path);
+    let search_area = fns::BracketSearchArea::new(&current_file.contents, first_row.y, last_row.y, language);
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(settings.bracket_highlight_color);
+    for cursor in visible_cursors {
+        let Some((bracket_pos, match_pos)) = search_area.find_matching_bracket(cursor.x, cursor.y) else {continue;};
+        for (x, y) in [bracket_pos, match_pos] {
+            let Some(row_num) = screen_rows.iter().position(|row| row.y == y && row.start_x <= x && x < row.end_x) else {continue;};
+            let row = &screen_rows[row_num];
+            let column = fns::get_column(&row.line, x, tab_width);
+            if column < row.start_column + scroll_column {continue;}
+            let screen_column = column - row.start_column - scroll_column;
+            render_rect_over_chars(screen_column, screen_column + 1, row_num, cursor_height, canvas, section, settings)?;
+        }
+    }
+    canvas.set_blend_mode(BlendMode::None);
+    Ok(())
+}
+
+
+
+// each row is laid out as "<offset>  <16 hex bytes, split in half>  <16 ascii chars>"
+pub fn render_hex_view<'a> (hex_view: &mut HexView, cursor_width: u32, cursor_height: u32, render_cursor_lines: bool, section: &Rect, font: &Font, canvas: &mut WindowCanvas, texture_creator: &'a TextureCreator<WindowContext>, textures: &mut ProgramTextures<'a>, settings: &ProgramSettings) -> Result<(), ProgramError> {
+
+    // keep the cursor on screen
+    let visible_rows = get_visible_line_count(section, settings).max(1);
+    let cursor_row = hex_view.cursor / HEX_VIEW_ROW_LEN;
+    if cursor_row < hex_view.scroll_row {hex_view.scroll_row = cursor_row;}
+    if cursor_row >= hex_view.scroll_row + visible_rows {hex_view.scroll_row = cursor_row + 1 - visible_rows;}
+
+    // render rows
+    let ascii_column = get_hex_view_column(HEX_VIEW_ROW_LEN) + 1;
+    let rows = hex_view.bytes.chunks(HEX_VIEW_ROW_LEN).enumerate().skip(hex_view.scroll_row).take(visible_rows);
+    for (screen_row, (row, row_bytes)) in rows.enumerate() {
+        let mut row_text = format!("{:08x}  ", row * HEX_VIEW_ROW_LEN).chars().collect::<Vec<char>>();
+        row_text.resize(ascii_column, ' ');
+        for (i, byte) in row_bytes.iter().enumerate() {
+            let hex_column = get_hex_view_column(i);
+            let hex_digits = format!("{byte:02x}").chars().collect::<Vec<char>>();
+            row_text[hex_column] = hex_digits[0];
+            row_text[hex_column + 1] = hex_digits[1];
+            row_text.push(if byte.is_ascii_graphic() || *byte == b' ' {*byte as char} else {'.'});
+        }
+        render_text_line(&row_text, screen_row, 0, settings.tab_width, section, font, canvas, texture_creator, textures, settings)?;
+    }
+
+    // render cursor
+    if hex_view.bytes.is_empty() {return Ok(());}
+    let cursor_screen_row = cursor_row - hex_view.scroll_row;
+    let cursor_column = hex_view.cursor % HEX_VIEW_ROW_LEN;
+    let hex_column = get_hex_view_column(cursor_column);
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(settings.cursor_selection_color);
+    render_rect_over_chars(hex_column, hex_column + 2, cursor_screen_row, cursor_height, canvas, section, settings)?;
+    render_rect_over_chars(ascii_column + cursor_column, ascii_column + cursor_column + 1, cursor_screen_row, cursor_height, canvas, section, settings)?;
+    canvas.set_blend_mode(BlendMode::None);
+    if render_cursor_lines {
+        let y_offset = (settings.font_size * 3 / 32) as i32;
+        let (cursor_x, cursor_y) = get_char_position(hex_column + hex_view.editing_low_nibble as usize, cursor_screen_row, section, settings);
+        canvas.set_draw_color(settings.cursor_color);
+        canvas.fill_rect(clamp_to_section(&Rect::new(cursor_x, cursor_y + y_offset, cursor_width, cursor_height), section).1)?;
+    }
+
+    Ok(())
+}
+
+pub fn get_hex_view_column (byte_index: usize) -> usize {
+    10 + byte_index * 3 + (byte_index >= HEX_VIEW_ROW_LEN / 2) as usize
+}
+
+
+
+pub fn render_history_panel<'a> (history: &EditHistory, section: &Rect, font: &Font, canvas: &mut WindowCanvas, texture_creator: &'a TextureCreator<WindowContext>, textures: &mut ProgramTextures<'a>, settings: &ProgramSettings) -> Result<(), ProgramError> {
+
+    let panel_width = section.width() * 2 / 5;
+    let panel = Rect::new(section.x() + (section.width() - panel_width) as i32, section.y(), panel_width, section.height());
+    canvas.set_draw_color(fns::blend_colors(settings.background_color, Color::RGB(0, 0, 0), 0.3));
+    canvas.fill_rect(panel)?;
+
+    // '>' marks the branch that redo follows, '*' marks other branches that contain the current state
+    let mut panel_lines = vec!(
+        format!("state {} of {}", history.current_step, history.steps.len() - 1),
+        String::new(),
+    );
+    for branch in history.get_branches() {
+        let marker = if branch.is_redo_branch {'>'} else if branch.contains_current_step {'*'} else {' '};
+        panel_lines.push(format!("{marker} #{} ({} steps) {}", branch.tip_step, branch.len, fns::format_time_ago(branch.time)));
+    }
+
+    for (i, current_line) in panel_lines.iter().enumerate() {
+        render_text_line(&current_line.chars().collect::<Vec<char>>(), i, 0, settings.tab_width, &panel, font, canvas, texture_creator, textures, settings)?;
+    }
+
+    Ok(())
+}
+
+
+
 pub fn render_rect_over_chars (x_pos_1: usize, x_pos_2: usize, y_pos: usize, char_height: u32, canvas: &mut WindowCanvas, section: &Rect, settings: &ProgramSettings) -> Result<(), ProgramError> {
     let y_offset = (settings.font_size * 3 / 32) as i32;
     let x_offset = -((settings.font_size * 1 / 32) as i32);
@@ -151,6 +390,7 @@ pub fn render_rect_over_chars (x_pos_1: usize, x_pos_2: usize, y_pos: usize, cha
 
 
 
+// char_x is a column, which is only the same as a char index when there are no wide characters
 pub fn get_char_position (char_x: usize, char_y: usize, section: &Rect, settings: &ProgramSettings) -> (i32, i32) {
     let padding = div(section.width(), 80.) as i32;
     let char_height = settings.font_size;
@@ -163,6 +403,50 @@ pub fn get_char_position (char_x: usize, char_y: usize, section: &Rect, settings
 
 
 
+// the inverse of get_char_position, which gives the nearest column boundary and the row (both can be outside of the section)
+pub fn get_char_at_position (pixel_x: i32, pixel_y: i32, section: &Rect, settings: &ProgramSettings) -> (isize, isize) {
+    let padding = div(section.width(), 80.) as i32;
+    let char_height = settings.font_size;
+    let char_width = (char_height * 11 / 16).max(1) as i32;
+    let char_spacing = ((char_height as f64 * settings.font_spacing) as i32).max(1);
+    let (section_x, section_y) = (pixel_x - section.x() - padding, pixel_y - section.y() - padding);
+    ((section_x + char_width / 2).div_euclid(char_width) as isize, section_y.div_euclid(char_spacing) as isize)
+}
+
+// returns (column, y), where the column is in the whole line and can be past the end of it or off the screen
+pub fn get_text_column_at_position (current_file: &File, pixel_x: i32, pixel_y: i32, wrap_width: Option<usize>, section: &Rect, settings: &ProgramSettings) -> (usize, usize) {
+    let (screen_column, screen_row) = get_char_at_position(pixel_x, pixel_y, section, settings);
+    // positions above or below the section are moved onto the first or last visible row
+    let screen_row = screen_row.clamp(0, get_visible_line_count(section, settings).max(1) as isize - 1) as usize;
+    let screen_rows = get_screen_rows(current_file, current_file.scroll_y as usize, screen_row + 1, wrap_width);
+    let Some(row) = screen_rows.last() else {return (0, current_file.contents.len_lines() - 1);};
+    let column = (row.start_column + current_file.scroll_x as usize).saturating_add_signed(screen_column);
+    // a wrapped row ends before the next row starts
+    if row.is_line_end {return (column, row.y);}
+    let row_end_column = fns::get_column(&row.line, row.end_x, current_file.tab_width);
+    (column.min(row_end_column.saturating_sub(1)), row.y)
+}
+
+
+
+
+
+pub fn get_visible_line_count (section: &Rect, settings: &ProgramSettings) -> usize {
+    let padding = div(section.width(), 80.);
+    let char_spacing = (settings.font_size as f64 * settings.font_spacing) as u32;
+    (section.height().saturating_sub(padding) / char_spacing.max(1)) as usize
+}
+
+pub fn get_visible_column_count (section: &Rect, settings: &ProgramSettings) -> usize {
+    let padding = div(section.width(), 80.);
+    let char_width = settings.font_size * 11 / 16;
+    (section.width().saturating_sub(padding * 2) / char_width.max(1)) as usize
+}
+
+
+
+
+
 pub fn clamp_to_section (rect: &Rect, section: &Rect) -> (Rect, Rect) {
     let (lx, ly) = (rect.x, rect.y);
     let (width, height) = (rect.width(), rect.height());
@@ -6,6 +6,14 @@ pub fn unwind (program_data: &ProgramData) -> Result<(), ProgramError> {
 
     // set continue details
 
+    // save undo histories
+    for file in program_data.files.read().iter() {
+        if let Err(error) = undo_journal::save_journal(file) {
+            println!("Warning: could not save undo history for {}", file.path);
+            println!("Error: {:?}", error);
+        }
+    }
+
     // save settings
 
     Ok(())
@@ -1,5 +1,6 @@
 use crate::prelude::*;
-use sdl2::{event::Event, keyboard::Keycode};
+use sdl2::{event::Event, keyboard::Keycode, mouse::MouseButton};
+use rand::seq::SliceRandom;
 
 
 
@@ -18,6 +19,10 @@ pub fn handle_event (event: Event, program_data: &ProgramData) -> Result<(), Pro
 
         Event::TextInput {text, timestamp, ..} => handle_text_input(&text, program_data, current_file, timestamp),
 
+        Event::MouseButtonDown {mouse_btn: MouseButton::Left, clicks, x, y, ..} => handle_mouse_down(x, y, clicks, program_data, current_file),
+        Event::MouseMotion {x, y, ..} => handle_mouse_motion(x, y, program_data, current_file),
+        Event::MouseButtonUp {mouse_btn: MouseButton::Left, ..} => handle_mouse_up(program_data, current_file),
+
         _ => Ok(())
 
     }
@@ -27,16 +32,33 @@ pub fn handle_event (event: Event, program_data: &ProgramData) -> Result<(), Pro
 
 
 
-pub fn handle_key_down (keycode: Keycode, _repeat: bool, program_data: &ProgramData, current_file: Option<&mut File>, timestamp: u32) -> Result<(), ProgramError> {
+pub fn handle_key_down (keycode: Keycode, _repeat: bool, program_data: &ProgramData, mut current_file: Option<&mut File>, timestamp: u32) -> Result<(), ProgramError> {
     if timestamp == *program_data.last_text_input_timestamp.read() {return Ok(());}
+    let keys_pressed = *program_data.keys_pressed.read();
+    if let Some(hex_view) = current_file.as_deref_mut().and_then(|file| file.hex_view.as_mut()) {
+        if handle_hex_view_key_down(keycode, program_data, hex_view) {return Ok(());}
+    }
     match keycode {
 
 
 
+        Keycode::Up   if keys_pressed.alt_pressed && keys_pressed.control_pressed && current_file.is_some() => add_cursor_vertically_fn(true , program_data, current_file.unwrap()),
+        Keycode::Down if keys_pressed.alt_pressed && keys_pressed.control_pressed && current_file.is_some() => add_cursor_vertically_fn(false, program_data, current_file.unwrap()),
+        Keycode::Up   if keys_pressed.alt_pressed && !keys_pressed.control_pressed && current_file.is_some() => run_line_fn_at_cursors(move_lines_up_fn  , program_data, current_file.unwrap()),
+        Keycode::Down if keys_pressed.alt_pressed && !keys_pressed.control_pressed && current_file.is_some() => run_line_fn_at_cursors(move_lines_down_fn, program_data, current_file.unwrap()),
+
+        Keycode::Left  if keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(move_cursor_word_left_fn , program_data, current_file.unwrap()),
+        Keycode::Right if keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(move_cursor_word_right_fn, program_data, current_file.unwrap()),
+        Keycode::Home  if keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(move_cursor_file_start_fn, program_data, current_file.unwrap()),
+        Keycode::End   if keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(move_cursor_file_end_fn  , program_data, current_file.unwrap()),
+        Keycode::PageUp   if current_file.is_some() => move_page_fn(true , program_data, current_file.unwrap()),
+        Keycode::PageDown if current_file.is_some() => move_page_fn(false, program_data, current_file.unwrap()),
+
         Keycode::Up    if current_file.is_some() => run_fn_at_cursors(move_cursor_up_fn   , program_data, current_file.unwrap()),
         Keycode::Down  if current_file.is_some() => run_fn_at_cursors(move_cursor_down_fn , program_data, current_file.unwrap()),
         Keycode::Left  if current_file.is_some() => run_fn_at_cursors(move_cursor_left_fn , program_data, current_file.unwrap()),
         Keycode::Right if current_file.is_some() => run_fn_at_cursors(move_cursor_right_fn, program_data, current_file.unwrap()),
+        Keycode::Home  if current_file.is_some() => run_fn_at_cursors(move_cursor_home_fn , program_data, current_file.unwrap()),
         Keycode::End   if current_file.is_some() => run_fn_at_cursors(move_cursor_end_fn  , program_data, current_file.unwrap()),
 
         Keycode::LShift | Keycode::RShift => {
@@ -56,9 +78,61 @@ pub fn handle_key_down (keycode: Keycode, _repeat: bool, program_data: &ProgramD
 
 
 
+        Keycode::Backspace if keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(delete_word_before_fn, program_data, current_file.unwrap()),
+        Keycode::Delete if keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(delete_word_after_fn, program_data, current_file.unwrap()),
         Keycode::Backspace if current_file.is_some() => run_fn_at_cursors(backspace_fn, program_data, current_file.unwrap()),
         Keycode::Delete if current_file.is_some() => run_fn_at_cursors(delete_fn, program_data, current_file.unwrap()),
         Keycode::Return if current_file.is_some() => run_fn_at_cursors(return_fn, program_data, current_file.unwrap()),
+        Keycode::Q if keys_pressed.alt_pressed && !keys_pressed.control_pressed && current_file.is_some() => run_fn_at_cursors(reflow_fn, program_data, current_file.unwrap()),
+        Keycode::Tab if keys_pressed.shift_pressed && current_file.is_some() => run_fn_at_cursors(outdent_lines_fn, program_data, current_file.unwrap()),
+        Keycode::Tab if current_file.is_some() => run_fn_at_cursors(tab_fn, program_data, current_file.unwrap()),
+
+        Keycode::Z if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() => go_to_earlier_step_fn(program_data, current_file.unwrap()),
+        Keycode::Y if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() => go_to_later_step_fn(program_data, current_file.unwrap()),
+        Keycode::B if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() => {
+            current_file.unwrap().history.switch_redo_branch();
+            Ok(())
+        }
+        Keycode::E if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() => toggle_line_ending_fn(current_file.unwrap()),
+        Keycode::W if keys_pressed.control_pressed && keys_pressed.alt_pressed => {
+            let mut soft_wrap = program_data.soft_wrap.write();
+            *soft_wrap = !*soft_wrap;
+            if let Some(current_file) = current_file {current_file.scroll_to_cursor = true;}
+            Ok(())
+        }
+        Keycode::N if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() => switch_save_encoding_fn(current_file.unwrap()),
+        Keycode::R if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() => reopen_with_next_encoding_fn(program_data, current_file.unwrap()),
+        Keycode::Num1 | Keycode::Num2 | Keycode::Num3 | Keycode::Num4 | Keycode::Num5 | Keycode::Num6 | Keycode::Num7 | Keycode::Num8 | Keycode::Num9
+            if keys_pressed.control_pressed && keys_pressed.alt_pressed && current_file.is_some() =>
+        {
+            let command_num = keycode as usize - Keycode::Num1 as usize;
+            filter_with_command_fn(command_num, program_data, current_file.unwrap())
+        }
+        Keycode::S if keys_pressed.control_pressed && current_file.is_some() => save_current_file_fn(program_data, current_file.unwrap()),
+        Keycode::D if keys_pressed.control_pressed && keys_pressed.shift_pressed && current_file.is_some() => run_line_fn_at_cursors(duplicate_lines_fn, program_data, current_file.unwrap()),
+        Keycode::D if keys_pressed.control_pressed && current_file.is_some() => select_next_occurrence_fn(program_data, current_file.unwrap()),
+        Keycode::L if keys_pressed.control_pressed && keys_pressed.shift_pressed && current_file.is_some() => select_all_occurrences_fn(program_data, current_file.unwrap()),
+        Keycode::K if keys_pressed.control_pressed && keys_pressed.shift_pressed && current_file.is_some() => run_line_fn_at_cursors(delete_lines_fn, program_data, current_file.unwrap()),
+        Keycode::Slash if keys_pressed.control_pressed && keys_pressed.shift_pressed && current_file.is_some() => toggle_block_comment_fn(program_data, current_file.unwrap()),
+        Keycode::Slash if keys_pressed.control_pressed && current_file.is_some() => toggle_line_comments_fn(program_data, current_file.unwrap()),
+        Keycode::M if keys_pressed.control_pressed && keys_pressed.shift_pressed && current_file.is_some() => select_between_brackets_fn(program_data, current_file.unwrap()),
+        Keycode::M if keys_pressed.control_pressed && current_file.is_some() => jump_to_matching_bracket_fn(program_data, current_file.unwrap()),
+        Keycode::J if keys_pressed.control_pressed && current_file.is_some() => run_line_fn_at_cursors(join_lines_fn, program_data, current_file.unwrap()),
+        Keycode::F9 if keys_pressed.shift_pressed && current_file.is_some() => run_lines_transform_fn(|lines, _| lines.sort_by_cached_key(|line| line.to_lowercase()), program_data, current_file.unwrap()),
+        Keycode::F9 if keys_pressed.control_pressed && current_file.is_some() => run_lines_transform_fn(|lines, _| lines.sort_by(|a, b| fns::natural_cmp(a, b)), program_data, current_file.unwrap()),
+        Keycode::F9 if keys_pressed.alt_pressed && current_file.is_some() => run_lines_transform_fn(|lines, settings| sort_lines_by_regex(lines, settings), program_data, current_file.unwrap()),
+        Keycode::F9 if current_file.is_some() => run_lines_transform_fn(|lines, _| lines.sort(), program_data, current_file.unwrap()),
+        Keycode::F10 if current_file.is_some() => run_lines_transform_fn(|lines, _| lines.reverse(), program_data, current_file.unwrap()),
+        Keycode::F11 if current_file.is_some() => run_lines_transform_fn(remove_duplicate_lines, program_data, current_file.unwrap()),
+        Keycode::F12 if current_file.is_some() => run_lines_transform_fn(|lines, _| lines.shuffle(&mut rand::thread_rng()), program_data, current_file.unwrap()),
+        Keycode::F2 => {
+            let mut show_history_panel = program_data.show_history_panel.write();
+            *show_history_panel = !*show_history_panel;
+            Ok(())
+        }
+        Keycode::Z if keys_pressed.control_pressed && keys_pressed.shift_pressed && current_file.is_some() => redo_fn(program_data, current_file.unwrap()),
+        Keycode::Z if keys_pressed.control_pressed && current_file.is_some() => undo_fn(program_data, current_file.unwrap()),
+        Keycode::Y if keys_pressed.control_pressed && current_file.is_some() => redo_fn(program_data, current_file.unwrap()),
 
 
 
@@ -76,6 +150,39 @@ pub fn handle_key_down (keycode: Keycode, _repeat: bool, program_data: &ProgramD
 
 
 
+// returns whether the key was used, and every key that would edit or move through the (empty) text buffer is used up here
+pub fn handle_hex_view_key_down (keycode: Keycode, program_data: &ProgramData, hex_view: &mut HexView) -> bool {
+    let keys_pressed = *program_data.keys_pressed.read();
+    let row_len = HEX_VIEW_ROW_LEN as isize;
+    match keycode {
+        Keycode::Up => hex_view.move_cursor(-row_len),
+        Keycode::Down => hex_view.move_cursor(row_len),
+        Keycode::Left => hex_view.move_cursor(-1),
+        Keycode::Right => hex_view.move_cursor(1),
+        Keycode::Home => hex_view.move_cursor(-(hex_view.cursor as isize % row_len)),
+        Keycode::End => hex_view.move_cursor(row_len - 1 - hex_view.cursor as isize % row_len),
+        Keycode::Backspace | Keycode::Delete if hex_view.is_mapped() => println!("Warning: bytes can't be removed from large files, they can only be overwritten"),
+        Keycode::Backspace if hex_view.cursor > 0 => {
+            hex_view.move_cursor(-1);
+            hex_view.remove_byte(hex_view.cursor);
+        }
+        Keycode::Delete => hex_view.remove_byte(hex_view.cursor),
+
+        // these don't touch the text, so they work the same as for text files
+        Keycode::LShift | Keycode::RShift | Keycode::LCtrl | Keycode::RCtrl | Keycode::LAlt | Keycode::RAlt | Keycode::Escape | Keycode::F2 => return false,
+        Keycode::S if keys_pressed.control_pressed => return false,
+        Keycode::W | Keycode::R if keys_pressed.control_pressed && keys_pressed.alt_pressed => return false,
+
+        _ => return true,
+    }
+    *program_data.cursor_place_instant.write() = Instant::now();
+    true
+}
+
+
+
+
+
 pub fn handle_key_up (keycode: Keycode, _repeat: bool, program_data: &ProgramData, current_file: Option<&mut File>) -> Result<(), ProgramError> {
     let Some(_current_file) = current_file else {return Ok(());};
     match keycode {
@@ -102,6 +209,304 @@ pub fn handle_key_up (keycode: Keycode, _repeat: bool, program_data: &ProgramDat
 
 
 
+// a double click selects a word and a triple click selects a line, while ctrl adds a cursor, shift extends the selection, and alt starts a box selection
+pub fn handle_mouse_down (pixel_x: i32, pixel_y: i32, clicks: u8, program_data: &ProgramData, current_file: Option<&mut File>) -> Result<(), ProgramError> {
+    let Some(current_file) = current_file else {return Ok(());};
+    if current_file.hex_view.is_some() {return Ok(());}
+    let Some((column, y)) = get_text_column_at_mouse(pixel_x, pixel_y, program_data, current_file) else {return Ok(());};
+    let keys_pressed = *program_data.keys_pressed.read();
+    let mut mouse_state = program_data.mouse_state.write();
+    (mouse_state.x, mouse_state.y) = (pixel_x, pixel_y);
+    *program_data.cursor_place_instant.write() = Instant::now();
+
+    if keys_pressed.alt_pressed {
+        mouse_state.box_selection_start = Some((column, y));
+        set_box_selection(current_file, (column, y), (column, y));
+        return Ok(());
+    }
+
+    let x = current_file.contents.get_x_at_column(column, y, current_file.tab_width);
+    let mut new_cursor = Cursor {
+        x,
+        y,
+        wanted_x: current_file.contents.get_column(x, y, current_file.tab_width),
+        selection_start: None,
+    };
+    if keys_pressed.shift_pressed && !keys_pressed.control_pressed {
+        if let Some(last_cursor) = current_file.cursors.pop() {
+            new_cursor.selection_start = Some(last_cursor.selection_start.unwrap_or((last_cursor.x, last_cursor.y)));
+        }
+    }
+    if keys_pressed.control_pressed {
+        current_file.cursors.push(new_cursor);
+    } else {
+        current_file.cursors = vec!(new_cursor);
+    }
+
+    let last_cursor_num = current_file.cursors.len() - 1;
+    match clicks {
+        0 | 1 => {}
+        2 => select_word_at_cursor(current_file, last_cursor_num),
+        _ => {
+            let line_end = if y + 1 < current_file.contents.len_lines() {(0, y + 1)} else {(current_file.contents.line_len(y), y)};
+            let cursor = &mut current_file.cursors[last_cursor_num];
+            cursor.selection_start = Some((0, y));
+            (cursor.x, cursor.y) = line_end;
+            cursor.wanted_x = current_file.contents.get_column(line_end.0, line_end.1, current_file.tab_width);
+        }
+    }
+    let last_cursor = &current_file.cursors[last_cursor_num];
+    mouse_state.drag_start = Some(last_cursor.selection_start.unwrap_or((last_cursor.x, last_cursor.y)));
+    Ok(())
+}
+
+pub fn handle_mouse_motion (pixel_x: i32, pixel_y: i32, program_data: &ProgramData, current_file: Option<&mut File>) -> Result<(), ProgramError> {
+    let mouse_state = {
+        let mut mouse_state = program_data.mouse_state.write();
+        (mouse_state.x, mouse_state.y) = (pixel_x, pixel_y);
+        *mouse_state
+    };
+    let Some(current_file) = current_file else {return Ok(());};
+    drag_to_mouse(mouse_state, program_data, current_file);
+    Ok(())
+}
+
+pub fn handle_mouse_up (program_data: &ProgramData, current_file: Option<&mut File>) -> Result<(), ProgramError> {
+    let mut mouse_state = program_data.mouse_state.write();
+    mouse_state.drag_start = None;
+    mouse_state.box_selection_start = None;
+    if let Some(current_file) = current_file {
+        remove_cursor_duplicates(&mut current_file.cursors);
+    }
+    Ok(())
+}
+
+// the last cursor's selection goes from where the drag started to the mouse
+pub fn drag_to_mouse (mouse_state: MouseState, program_data: &ProgramData, current_file: &mut File) {
+    if mouse_state.drag_start.is_none() && mouse_state.box_selection_start.is_none() {return;}
+    let Some((column, y)) = get_text_column_at_mouse(mouse_state.x, mouse_state.y, program_data, current_file) else {return;};
+    if let Some(box_selection_start) = mouse_state.box_selection_start {
+        set_box_selection(current_file, box_selection_start, (column, y));
+    } else if let Some(drag_start) = mouse_state.drag_start {
+        let x = current_file.contents.get_x_at_column(column, y, current_file.tab_width);
+        let Some(cursor) = current_file.cursors.last_mut() else {return;};
+        (cursor.x, cursor.y) = (x, y);
+        cursor.selection_start = fns::some_if(drag_start != (x, y), || drag_start);
+        cursor.wanted_x = current_file.contents.get_column(x, y, current_file.tab_width);
+    }
+    *program_data.cursor_place_instant.write() = Instant::now();
+}
+
+
+
+pub const AUTO_SCROLL_SPEED: f64 = 10.; // in lines per second, and it gets faster the further the mouse is past the edge
+
+// this runs every frame, so the view keeps scrolling while the mouse is held still past the edge of the text
+pub fn update_mouse_drag (program_data: &ProgramData) -> Result<(), ProgramError> {
+    let (mouse_state, elapsed_time) = {
+        let mut mouse_state = program_data.mouse_state.write();
+        let elapsed_time = mouse_state.last_auto_scroll_instant.elapsed().as_secs_f64();
+        mouse_state.last_auto_scroll_instant = Instant::now();
+        (*mouse_state, elapsed_time)
+    };
+    if mouse_state.drag_start.is_none() && mouse_state.box_selection_start.is_none() {return Ok(());}
+    let Some(text_section) = *program_data.text_section.read() else {return Ok(());};
+    let mut files = program_data.files.write();
+    let Some(current_file) = fns::get_current_file_mut(program_data, &mut files)? else {return Ok(());};
+
+    let settings_mutex = program_data.settings.read();
+    let settings = settings_mutex.as_ref().expect("Error: settings is none");
+    let line_height = settings.font_size as f64 * settings.font_spacing;
+    let char_width = (settings.font_size * 11 / 16) as f64;
+    let get_scroll_amount = |distance: i32, char_size: f64| AUTO_SCROLL_SPEED * (1. + distance as f64 / char_size.max(1.)) * elapsed_time;
+    let (old_scroll_x, old_scroll_y) = (current_file.scroll_x, current_file.scroll_y);
+    if mouse_state.y < text_section.top() {
+        current_file.scroll_y -= get_scroll_amount(text_section.top() - mouse_state.y, line_height);
+    } else if mouse_state.y >= text_section.bottom() {
+        current_file.scroll_y += get_scroll_amount(mouse_state.y - text_section.bottom(), line_height);
+    }
+    current_file.scroll_y = current_file.scroll_y.clamp(0., (current_file.contents.len_lines() - 1) as f64);
+    if fns::get_wrap_width(program_data).is_none() {
+        if mouse_state.x < text_section.left() {
+            current_file.scroll_x -= get_scroll_amount(text_section.left() - mouse_state.x, char_width);
+        } else if mouse_state.x >= text_section.right() {
+            current_file.scroll_x += get_scroll_amount(mouse_state.x - text_section.right(), char_width);
+        }
+        current_file.scroll_x = current_file.scroll_x.max(0.);
+    }
+    drop(settings_mutex);
+
+    if (current_file.scroll_x, current_file.scroll_y) != (old_scroll_x, old_scroll_y) {
+        drag_to_mouse(mouse_state, program_data, current_file);
+    }
+    Ok(())
+}
+
+// returns (column, line)
+pub fn get_text_column_at_mouse (pixel_x: i32, pixel_y: i32, program_data: &ProgramData, current_file: &File) -> Option<(usize, usize)> {
+    let text_section = (*program_data.text_section.read())?;
+    let settings = program_data.settings.read();
+    let settings = settings.as_ref().expect("Error: settings is none");
+    let wrap_width = fns::get_wrap_width(program_data);
+    Some(render::get_text_column_at_position(current_file, pixel_x, pixel_y, wrap_width, &text_section, settings))
+}
+
+// every line between start and end gets a cursor that selects the same columns, and lines that are too short get a cursor at their end
+pub fn set_box_selection (current_file: &mut File, start: (usize, usize), end: (usize, usize)) {
+    let (start_column, start_y) = start;
+    let (end_column, end_y) = end;
+    let contents = &current_file.contents;
+    let mut new_cursors = (start_y.min(end_y)..=start_y.max(end_y))
+        .map(|y| {
+            let selection_x = contents.get_x_at_column(start_column, y, current_file.tab_width);
+            let x = contents.get_x_at_column(end_column, y, current_file.tab_width);
+            Cursor {
+                x,
+                y,
+                wanted_x: end_column,
+                selection_start: fns::some_if(selection_x != x, || (selection_x, y)),
+            }
+        })
+        .collect::<Vec<Cursor>>();
+    // the last cursor is the one that's scrolled to, so it should be on the line with the mouse
+    if end_y < start_y {new_cursors.reverse();}
+    current_file.cursors = new_cursors;
+}
+
+
+
+// the first press selects the word at each cursor, then every press adds a cursor at the next occurrence of the last selection
+pub fn select_next_occurrence_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let Some(last_cursor) = current_file.cursors.last() else {return Ok(());};
+    let Some((start, end)) = get_selection_range(last_cursor) else {
+        for cursor_num in 0..current_file.cursors.len() {
+            select_word_at_cursor(current_file, cursor_num);
+        }
+        *program_data.cursor_place_instant.write() = Instant::now();
+        return Ok(());
+    };
+
+    // searching has to decode the whole file, which is too slow for large files
+    if current_file.contents.is_lazy() {
+        println!("Warning: selecting occurrences isn't supported for large files");
+        return Ok(());
+    }
+
+    // the search starts after the last selection and wraps around the end of the file
+    let occurrences = get_occurrences(&current_file.contents, start, end);
+    let is_after_end = |(occurrence_start, _): &&((usize, usize), (usize, usize))| (occurrence_start.1, occurrence_start.0) >= (end.1, end.0);
+    let is_selected = |occurrence: &((usize, usize), (usize, usize))| current_file.cursors.iter().any(|cursor| get_selection_range(cursor) == Some(*occurrence));
+    let next_occurrence = occurrences.iter().filter(is_after_end)
+        .chain(occurrences.iter().filter(|occurrence| !is_after_end(occurrence)))
+        .find(|occurrence| !is_selected(occurrence));
+    let Some((next_start, next_end)) = next_occurrence.copied() else {return Ok(());};
+
+    current_file.cursors.push(Cursor {
+        x: next_end.0,
+        y: next_end.1,
+        wanted_x: current_file.contents.get_column(next_end.0, next_end.1, current_file.tab_width),
+        selection_start: Some(next_start),
+    });
+    current_file.scroll_to_cursor = true;
+    *program_data.cursor_place_instant.write() = Instant::now();
+    Ok(())
+}
+
+// replaces every cursor with one on each occurrence of the last cursor's selection (or the word at it)
+pub fn select_all_occurrences_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    if current_file.cursors.is_empty() {return Ok(());}
+    if current_file.contents.is_lazy() {
+        println!("Warning: selecting occurrences isn't supported for large files");
+        return Ok(());
+    }
+    let last_cursor_num = current_file.cursors.len() - 1;
+    select_word_at_cursor(current_file, last_cursor_num);
+    let Some((start, end)) = get_selection_range(&current_file.cursors[last_cursor_num]) else {return Ok(());};
+
+    // the last cursor stays last, so that the view doesn't jump to a different occurrence
+    let mut occurrences = get_occurrences(&current_file.contents, start, end);
+    occurrences.retain(|occurrence| *occurrence != (start, end));
+    occurrences.push((start, end));
+    current_file.cursors = occurrences.into_iter()
+        .map(|(occurrence_start, occurrence_end)| Cursor {
+            x: occurrence_end.0,
+            y: occurrence_end.1,
+            wanted_x: current_file.contents.get_column(occurrence_end.0, occurrence_end.1, current_file.tab_width),
+            selection_start: Some(occurrence_start),
+        })
+        .collect();
+    *program_data.cursor_place_instant.write() = Instant::now();
+    Ok(())
+}
+
+// does nothing if the cursor already has a selection or isn't at a word
+pub fn select_word_at_cursor (current_file: &mut File, cursor_num: usize) {
+    let cursor = &current_file.cursors[cursor_num];
+    if cursor.selection_start.is_some() {return;}
+    let Some((word_start, word_end)) = fns::get_word_range(&current_file.contents.line(cursor.y), cursor.x) else {return;};
+    let cursor = &mut current_file.cursors[cursor_num];
+    cursor.selection_start = Some((word_start, cursor.y));
+    cursor.x = word_end;
+    cursor.wanted_x = current_file.contents.get_column(word_end, cursor.y, current_file.tab_width);
+}
+
+// returns every (start, end) where the text between start and end appears, and whole words only match whole words
+pub fn get_occurrences (contents: &TextBuffer, start: (usize, usize), end: (usize, usize)) -> Vec<((usize, usize), (usize, usize))> {
+    let pattern = contents.slice(start, end);
+    if pattern.is_empty() {return vec!();}
+    let char_before = if start.0 > 0 {contents.char_at(start.0 - 1, start.1)} else {None};
+    let is_whole_word = pattern.chars().all(fns::is_word_char)
+        && !char_before.is_some_and(fns::is_word_char)
+        && !contents.char_at(end.0, end.1).is_some_and(fns::is_word_char);
+
+    let text = contents.to_string();
+    let (mut char_index, mut last_byte_index) = (0, 0);
+    text.match_indices(&pattern)
+        .filter_map(|(byte_index, _)| {
+            char_index += text[last_byte_index..byte_index].chars().count();
+            last_byte_index = byte_index;
+            if is_whole_word {
+                let char_before = text[..byte_index].chars().next_back();
+                let char_after = text[byte_index + pattern.len() ..].chars().next();
+                if char_before.is_some_and(fns::is_word_char) || char_after.is_some_and(fns::is_word_char) {return None;}
+            }
+            let occurrence_start = contents.char_index_to_pos(char_index);
+            Some((occurrence_start, fns::get_text_end_pos(occurrence_start, &pattern)))
+        })
+        .collect()
+}
+
+
+
+// the new cursor goes on the line above the top cursor, or below the bottom one
+pub fn add_cursor_vertically_fn (add_above: bool, program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let edge_cursor = if add_above {
+        current_file.cursors.iter().min_by_key(|cursor| cursor.y)
+    } else {
+        current_file.cursors.iter().max_by_key(|cursor| cursor.y)
+    };
+    let Some(edge_cursor) = edge_cursor else {return Ok(());};
+    let new_y = if add_above {
+        let Some(new_y) = edge_cursor.y.checked_sub(1) else {return Ok(());};
+        new_y
+    } else {
+        if edge_cursor.y + 1 >= current_file.contents.len_lines() {return Ok(());}
+        edge_cursor.y + 1
+    };
+    let wanted_x = edge_cursor.wanted_x;
+    current_file.cursors.push(Cursor {
+        x: current_file.contents.get_x_at_column(wanted_x, new_y, current_file.tab_width),
+        y: new_y,
+        wanted_x,
+        selection_start: None,
+    });
+    current_file.scroll_to_cursor = true;
+    *program_data.cursor_place_instant.write() = Instant::now();
+    Ok(())
+}
+
+
+
 pub fn handle_esc_pressed (program_data: &ProgramData) -> Result<(), ProgramError> {
     *program_data.exit.write() = true;
     Ok(())
@@ -111,31 +516,373 @@ pub fn handle_esc_pressed (program_data: &ProgramData) -> Result<(), ProgramErro
 
 
 
+pub fn save_current_file_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let Some(file_num) = *program_data.current_file_num.read() else {return Ok(());};
+    program_data.tasks.write().push(ProgramTask::SaveFile {file_num, file_path: current_file.path.to_string()});
+    Ok(())
+}
+
+
+
+// the new line ending is used the next time the file is saved
+pub fn toggle_line_ending_fn (current_file: &mut File) -> Result<(), ProgramError> {
+    current_file.line_ending = match current_file.line_ending {
+        LineEnding::Lf => LineEnding::CrLf,
+        LineEnding::CrLf => LineEnding::Lf,
+    };
+    Ok(())
+}
+
+
+
+
+
+// the new encoding is used the next time the file is saved
+pub fn switch_save_encoding_fn (current_file: &mut File) -> Result<(), ProgramError> {
+    current_file.encoding = current_file.encoding.next();
+    Ok(())
+}
+
+
+
+// every selection is filtered on its own, and the whole file is filtered if nothing is selected
+pub fn filter_with_command_fn (command_num: usize, program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let Some(file_num) = *program_data.current_file_num.read() else {return Ok(());};
+    let settings = program_data.settings.read();
+    let Some(command) = settings.as_ref().expect("Error: settings is none").filter_commands.get(command_num).cloned() else {
+        println!("Warning: there is no filter command number {}", command_num + 1);
+        return Ok(());
+    };
+    drop(settings);
+
+    let mut selections = current_file.cursors.iter().enumerate()
+        .filter_map(|(i, cursor)| get_selection_range(cursor).map(|(start, end)| (i, start, end)))
+        .collect::<Vec<(usize, (usize, usize), (usize, usize))>>();
+    selections.sort_by_key(|(_, (start_x, start_y), _)| (*start_y, *start_x));
+    let mut ranges: Vec<FilterRange> = vec!();
+    for (cursor_num, start, end) in selections {
+        let overlaps_last = ranges.last().is_some_and(|last_range| (start.1, start.0) < (last_range.end.1, last_range.end.0));
+        if overlaps_last {continue;}
+        ranges.push(FilterRange {cursor_num: Some(cursor_num), start, end, text: current_file.contents.slice(start, end)});
+    }
+    if ranges.is_empty() {
+        let end = current_file.contents.end_pos();
+        ranges.push(FilterRange {cursor_num: None, start: (0, 0), end, text: current_file.contents.slice((0, 0), end)});
+    }
+
+    program_data.tasks.write().push(ProgramTask::FilterText {file_num, file_path: current_file.path.to_string(), command, ranges});
+    Ok(())
+}
+
+
+
+pub fn reopen_with_next_encoding_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let Some(file_num) = *program_data.current_file_num.read() else {return Ok(());};
+    if current_file.history.current_step != current_file.history.saved_step {
+        println!("Warning: {} has unsaved changes, save it before changing the encoding", current_file.path);
+        return Ok(());
+    }
+    program_data.tasks.write().push(ProgramTask::ReloadWithEncoding {file_num, encoding: current_file.encoding.next()});
+    Ok(())
+}
+
+
+
+
+
 pub fn run_fn_at_cursors (cursor_fn: impl Fn(&mut File, usize, &ProgramData) -> Result<(), ProgramError>, program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    run_edit_fn_at_cursors(cursor_fn, EditKind::Other, program_data, current_file)
+}
+
+// every edit made during one call is grouped into a single undo step
+pub fn run_edit_fn_at_cursors (cursor_fn: impl Fn(&mut File, usize, &ProgramData) -> Result<(), ProgramError>, edit_kind: EditKind, program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    current_file.history.start_step(&current_file.cursors);
+    let mut result = Ok(());
     for i in 0..current_file.cursors.len() {
-        cursor_fn(current_file, i, program_data)?
+        result = cursor_fn(current_file, i, program_data);
+        if result.is_err() {break;}
     }
     remove_cursor_duplicates(&mut current_file.cursors);
+    current_file.history.finish_step(&current_file.cursors, edit_kind);
+    current_file.scroll_to_cursor = true;
     *program_data.cursor_place_instant.write() = Instant::now();
+    result
+}
+
+
+
+// line fns are given the merged line range of a group of cursors
+pub fn run_line_fn_at_cursors (line_fn: impl Fn(&mut File, usize, usize, &[usize]), program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    // every group is handled when running for the first cursor, since the groups have to be found before anything changes
+    let line_fn_at_cursors = |file: &mut File, cursor_num: usize, _program_data: &ProgramData| {
+        if cursor_num > 0 {return Ok(());}
+        // this goes from the bottom up so that each edit only moves lines that are already done
+        for (first_y, last_y, group) in get_line_groups(&file.cursors).into_iter().rev() {
+            line_fn(file, first_y, last_y, &group);
+        }
+        Ok(())
+    };
+    run_fn_at_cursors(line_fn_at_cursors, program_data, current_file)
+}
+
+// cursors with overlapping or touching lines are merged, so that no line gets changed twice
+pub fn get_line_groups (cursors: &[Cursor]) -> Vec<(usize, usize, Vec<usize>)> {
+    let mut cursor_lines = cursors.iter().enumerate()
+        .map(|(i, cursor)| {
+            let (first_y, last_y) = get_cursor_lines(cursor);
+            (first_y, last_y, i)
+        })
+        .collect::<Vec<(usize, usize, usize)>>();
+    cursor_lines.sort();
+    let mut groups: Vec<(usize, usize, Vec<usize>)> = vec!();
+    for (first_y, last_y, cursor_num) in cursor_lines {
+        match groups.last_mut() {
+            Some((_, group_last_y, group)) if first_y <= *group_last_y + 1 => {
+                *group_last_y = (*group_last_y).max(last_y);
+                group.push(cursor_num);
+            }
+            _ => groups.push((first_y, last_y, vec!(cursor_num))),
+        }
+    }
+    groups
+}
+
+// puts the group's cursors back where they were before the edit, but moved by some number of lines
+pub fn move_cursor_group (current_file: &mut File, group: &[usize], old_cursors: &[Cursor], amount: isize) {
+    for cursor_num in group {
+        let mut cursor = old_cursors[*cursor_num].clone();
+        cursor.y = cursor.y.saturating_add_signed(amount);
+        if let Some((_, selection_y)) = &mut cursor.selection_start {
+            *selection_y = selection_y.saturating_add_signed(amount);
+        }
+        current_file.cursors[*cursor_num] = cursor;
+    }
+}
+
+
+
+pub fn move_lines_up_fn (current_file: &mut File, first_y: usize, last_y: usize, group: &[usize]) {
+    if first_y == 0 {return;}
+    let old_cursors = current_file.cursors.clone();
+    let line_above = current_file.contents.line(first_y - 1).iter().collect::<String>();
+    current_file.remove_text((0, first_y - 1), (0, first_y));
+    let new_last_len = current_file.contents.line_len(last_y - 1);
+    current_file.insert_text(new_last_len, last_y - 1, &format!("\n{line_above}"));
+    move_cursor_group(current_file, group, &old_cursors, -1);
+}
+
+pub fn move_lines_down_fn (current_file: &mut File, first_y: usize, last_y: usize, group: &[usize]) {
+    if last_y + 1 >= current_file.contents.len_lines() {return;}
+    let old_cursors = current_file.cursors.clone();
+    let line_below = current_file.contents.line(last_y + 1).iter().collect::<String>();
+    let last_len = current_file.contents.line_len(last_y);
+    current_file.remove_text((last_len, last_y), (line_below.chars().count(), last_y + 1));
+    current_file.insert_text(0, first_y, &format!("{line_below}\n"));
+    move_cursor_group(current_file, group, &old_cursors, 1);
+}
+
+// the cursors end up on the new copy, which is below the original
+pub fn duplicate_lines_fn (current_file: &mut File, first_y: usize, last_y: usize, group: &[usize]) {
+    let old_cursors = current_file.cursors.clone();
+    let last_len = current_file.contents.line_len(last_y);
+    let lines = current_file.contents.slice((0, first_y), (last_len, last_y));
+    current_file.insert_text(last_len, last_y, &format!("\n{lines}"));
+    move_cursor_group(current_file, group, &old_cursors, (last_y - first_y + 1) as isize);
+}
+
+// the cursors keep their column, which has to be saved before the removal moves them
+pub fn delete_lines_fn (current_file: &mut File, first_y: usize, last_y: usize, group: &[usize]) {
+    let wanted_xs = group.iter().map(|cursor_num| current_file.cursors[*cursor_num].wanted_x).collect::<Vec<usize>>();
+    let contents = &current_file.contents;
+    let last_len = contents.line_len(last_y);
+    // the line break that gets removed is after the lines if possible, otherwise it's before them
+    let (start, end) = if last_y + 1 < contents.len_lines() {
+        ((0, first_y), (0, last_y + 1))
+    } else if first_y > 0 {
+        ((contents.line_len(first_y - 1), first_y - 1), (last_len, last_y))
+    } else {
+        ((0, 0), (last_len, last_y))
+    };
+    current_file.remove_text(start, end);
+
+    let new_y = first_y.min(current_file.contents.len_lines() - 1);
+    for (cursor_num, wanted_x) in group.iter().zip(wanted_xs) {
+        let cursor = &mut current_file.cursors[*cursor_num];
+        cursor.y = new_y;
+        cursor.x = current_file.contents.get_x_at_column(wanted_x, new_y, current_file.tab_width);
+        cursor.wanted_x = wanted_x;
+        cursor.selection_start = None;
+    }
+}
+
+// a single line gets joined with the line after it, and the next line's indentation is replaced with a space
+pub fn join_lines_fn (current_file: &mut File, first_y: usize, last_y: usize, group: &[usize]) {
+    let join_end_y = if first_y == last_y {last_y + 1} else {last_y};
+    if join_end_y >= current_file.contents.len_lines() {return;}
+
+    let mut join_x = 0;
+    for _ in first_y..join_end_y {
+        let line = current_file.contents.line(first_y);
+        let next_line = current_file.contents.line(first_y + 1);
+        let indent_len = next_line.iter().take_while(|char| char.is_whitespace()).count();
+        let needs_space = !line.is_empty() && !line.last().is_some_and(|char| char.is_whitespace()) && indent_len < next_line.len();
+        current_file.remove_text((line.len(), first_y), (indent_len, first_y + 1));
+        if needs_space {current_file.insert_text(line.len(), first_y, " ");}
+        join_x = line.len();
+    }
+
+    for cursor_num in group {
+        let cursor = &mut current_file.cursors[*cursor_num];
+        (cursor.x, cursor.y) = (join_x, first_y);
+        cursor.wanted_x = current_file.contents.get_column(join_x, first_y, current_file.tab_width);
+        cursor.selection_start = None;
+    }
+}
+
+
+
+// this works on the lines that each selection covers, or on the whole file if nothing is selected
+pub fn run_lines_transform_fn (transform_fn: impl Fn(&mut Vec<String>, &ProgramSettings), program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let settings = program_data.settings.read();
+    let settings = settings.as_ref().expect("Error: settings is none");
+    let transform_lines = |file: &mut File, first_y: usize, last_y: usize, group: &[usize]| {
+        let old_lines = (first_y..=last_y).map(|y| file.contents.line(y).iter().collect()).collect::<Vec<String>>();
+        let mut new_lines = old_lines.clone();
+        transform_fn(&mut new_lines, settings);
+        if new_lines == old_lines {return;}
+        let old_cursors = file.cursors.clone();
+        file.remove_text((0, first_y), (file.contents.line_len(last_y), last_y));
+        file.insert_text(0, first_y, &new_lines.join("\n"));
+
+        // positions inside the lines are kept on the same line number, but they have to stay inside the new lines
+        let new_last_y = first_y + new_lines.len() - 1;
+        let contents = &file.contents;
+        let move_pos = |(x, y): (usize, usize)| {
+            let new_y = y.min(new_last_y);
+            (x.min(contents.line_len(new_y)), new_y)
+        };
+        for cursor_num in group {
+            let old_cursor = &old_cursors[*cursor_num];
+            let cursor = &mut file.cursors[*cursor_num];
+            (cursor.x, cursor.y) = move_pos((old_cursor.x, old_cursor.y));
+            cursor.selection_start = old_cursor.selection_start.map(move_pos);
+            cursor.wanted_x = contents.get_column(cursor.x, cursor.y, file.tab_width);
+        }
+    };
+
+    if current_file.cursors.iter().any(|cursor| cursor.selection_start.is_some()) {
+        return run_line_fn_at_cursors(transform_lines, program_data, current_file);
+    }
+    let transform_file = |file: &mut File, cursor_num: usize, _program_data: &ProgramData| {
+        if cursor_num > 0 {return Ok(());}
+        let all_cursors = (0..file.cursors.len()).collect::<Vec<usize>>();
+        // the empty line after a trailing line break isn't included
+        let mut last_y = file.contents.len_lines() - 1;
+        if last_y > 0 && file.contents.line_len(last_y) == 0 {last_y -= 1;}
+        transform_lines(file, 0, last_y, &all_cursors);
+        Ok(())
+    };
+    run_fn_at_cursors(transform_file, program_data, current_file)
+}
+
+// lines that don't match are put first
+pub fn sort_lines_by_regex (lines: &mut [String], settings: &ProgramSettings) {
+    lines.sort_by_cached_key(|line| {
+        let captures = settings.sort_regex.captures(line);
+        captures.and_then(|captures| captures.get(1).or(captures.get(0))).map(|sort_key| sort_key.as_str().to_string())
+    });
+}
+
+// only the first copy of each line is kept
+pub fn remove_duplicate_lines (lines: &mut Vec<String>, _settings: &ProgramSettings) {
+    let mut seen_lines = std::collections::HashSet::new();
+    lines.retain(|line| seen_lines.insert(line.to_string()));
+}
+
+
+
+pub fn undo_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    if current_file.undo() {
+        *program_data.cursor_place_instant.write() = Instant::now();
+    }
     Ok(())
 }
 
+pub fn redo_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    if current_file.redo() {
+        *program_data.cursor_place_instant.write() = Instant::now();
+    }
+    Ok(())
+}
 
+pub fn go_to_earlier_step_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    if current_file.go_to_earlier_step() {
+        *program_data.cursor_place_instant.write() = Instant::now();
+    }
+    Ok(())
+}
 
+pub fn go_to_later_step_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    if current_file.go_to_later_step() {
+        *program_data.cursor_place_instant.write() = Instant::now();
+    }
+    Ok(())
+}
+
+
+
+// cursors at the same place are merged, and so are cursors whose selections overlap
+// the cursors are checked in the order of their ranges so that it's one pass, but the cursors that are left stay in their original order
 pub fn remove_cursor_duplicates (cursors: &mut Vec<Cursor>) {
-    // this is O(n^2), but it should be fine
-    let mut cursors_to_remove = vec!();
-    for (i, cursor_1) in cursors.iter().enumerate() {
-        'inner: for cursor_2 in cursors.iter().skip(i + 1) {
-            if cursor_1.x == cursor_2.x && cursor_1.y == cursor_2.y {
-                cursors_to_remove.push(i);
-                break 'inner;
+    let mut sorted_cursor_nums = (0..cursors.len()).collect::<Vec<usize>>();
+    sorted_cursor_nums.sort_by_key(|cursor_num| {
+        let ((start_x, start_y), (end_x, end_y)) = get_cursor_range(&cursors[*cursor_num]);
+        ((start_y, start_x), (end_y, end_x))
+    });
+
+    // each merged cursor is kept where the last of its cursors was
+    let mut merged_cursors: Vec<(Cursor, usize)> = vec!();
+    for cursor_num in sorted_cursor_nums {
+        let cursor = &cursors[cursor_num];
+        match merged_cursors.last_mut() {
+            Some((merged_cursor, merged_cursor_num)) if do_cursors_overlap(merged_cursor, cursor) => {
+                if cursor_num > *merged_cursor_num {
+                    let mut new_cursor = cursor.clone();
+                    merge_cursors(&mut new_cursor, merged_cursor);
+                    (*merged_cursor, *merged_cursor_num) = (new_cursor, cursor_num);
+                } else {
+                    merge_cursors(merged_cursor, cursor);
+                }
             }
+            _ => merged_cursors.push((cursor.clone(), cursor_num)),
         }
     }
-    for cursor_index in cursors_to_remove.iter().rev() {
-        cursors.remove(*cursor_index);
-    }
+
+    if merged_cursors.len() == cursors.len() {return;}
+    merged_cursors.sort_by_key(|(_, cursor_num)| *cursor_num);
+    *cursors = merged_cursors.into_iter().map(|(cursor, _)| cursor).collect();
+}
+
+// selections that only touch don't overlap
+pub fn do_cursors_overlap (cursor_1: &Cursor, cursor_2: &Cursor) -> bool {
+    let ((start_1_x, start_1_y), (end_1_x, end_1_y)) = get_cursor_range(cursor_1);
+    let ((start_2_x, start_2_y), (end_2_x, end_2_y)) = get_cursor_range(cursor_2);
+    let (start_1, end_1, start_2, end_2) = ((start_1_y, start_1_x), (end_1_y, end_1_x), (start_2_y, start_2_x), (end_2_y, end_2_x));
+    (start_1 == start_2 && end_1 == end_2) || (start_1 < end_2 && start_2 < end_1)
+}
+
+// the merged cursor covers both ranges, and keeps the direction of whichever one has a selection
+pub fn merge_cursors (kept_cursor: &mut Cursor, removed_cursor: &Cursor) {
+    let (kept_start, kept_end) = get_cursor_range(kept_cursor);
+    let (removed_start, removed_end) = get_cursor_range(removed_cursor);
+    let start = [kept_start, removed_start].into_iter().min_by_key(|(x, y)| (*y, *x)).unwrap();
+    let end = [kept_end, removed_end].into_iter().max_by_key(|(x, y)| (*y, *x)).unwrap();
+    if start == end {return;}
+    let direction_cursor = if kept_cursor.selection_start.is_some() {&*kept_cursor} else {removed_cursor};
+    let cursor_is_at_start = direction_cursor.selection_start.is_some_and(|(selection_x, selection_y)| (direction_cursor.y, direction_cursor.x) < (selection_y, selection_x));
+    let (cursor_pos, selection_start) = if cursor_is_at_start {(start, end)} else {(end, start)};
+    (kept_cursor.x, kept_cursor.y) = cursor_pos;
+    kept_cursor.selection_start = Some(selection_start);
 }
 
 
@@ -145,9 +892,12 @@ pub fn remove_cursor_duplicates (cursors: &mut Vec<Cursor>) {
 pub fn move_cursor_up_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
     let mut cursor = &mut current_file.cursors[cursor_num];
     handle_cursor_selection_on_move(cursor, program_data);
+    if let Some(wrap_width) = fns::get_wrap_width(program_data) {
+        move_cursor_by_row(current_file, cursor_num, wrap_width, true);
+        return Ok(());
+    }
     cursor.y = cursor.y.max(1) - 1;
-    let max_x = current_file.contents[cursor.y].len();
-    cursor.x = cursor.wanted_x.min(max_x);
+    cursor.x = current_file.contents.get_x_at_column(cursor.wanted_x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
@@ -156,28 +906,66 @@ pub fn move_cursor_up_fn (current_file: &mut File, cursor_num: usize, program_da
 pub fn move_cursor_down_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
     let mut cursor = &mut current_file.cursors[cursor_num];
     handle_cursor_selection_on_move(cursor, program_data);
-    let max_y = current_file.contents.len() as isize - 1;
+    if let Some(wrap_width) = fns::get_wrap_width(program_data) {
+        move_cursor_by_row(current_file, cursor_num, wrap_width, false);
+        return Ok(());
+    }
+    let max_y = current_file.contents.len_lines() as isize - 1;
     cursor.y = ((cursor.y as isize).min(max_y - 1) + 1) as usize;
-    let max_x = current_file.contents[cursor.y].len();
-    cursor.x = cursor.wanted_x.min(max_x);
+    cursor.x = current_file.contents.get_x_at_column(cursor.wanted_x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
 
 
+// with soft wrap, up and down move through the rows on the screen instead of through lines
+pub fn move_cursor_by_row (current_file: &mut File, cursor_num: usize, wrap_width: usize, move_up: bool) {
+    let tab_width = current_file.tab_width;
+    let contents = &current_file.contents;
+    let cursor = &mut current_file.cursors[cursor_num];
+    let line = contents.line(cursor.y);
+    let wrapped_rows = fns::get_wrapped_rows(&line, wrap_width, tab_width);
+    let row_num = fns::get_wrapped_row_num(&wrapped_rows, cursor.x);
+    // wanted_x is kept relative to the start of the row, so moving past shorter rows doesn't lose it
+    let wanted_row_column = cursor.wanted_x.saturating_sub(wrapped_rows[row_num].1);
+
+    let (new_y, new_row_num) = match move_up {
+        true if row_num > 0 => (cursor.y, row_num - 1),
+        true if cursor.y > 0 => (cursor.y - 1, usize::MAX),
+        false if row_num + 1 < wrapped_rows.len() => (cursor.y, row_num + 1),
+        false if cursor.y + 1 < contents.len_lines() => (cursor.y + 1, 0),
+        _ => (cursor.y, row_num),
+    };
+    let new_line = contents.line(new_y);
+    let new_wrapped_rows = fns::get_wrapped_rows(&new_line, wrap_width, tab_width);
+    let new_row_num = new_row_num.min(new_wrapped_rows.len() - 1);
+    let (row_start_x, row_start_column) = new_wrapped_rows[new_row_num];
+
+    // the end of a row that isn't the last one would be shown on the next row, so it stops one grapheme before that
+    let row_max_x = match new_wrapped_rows.get(new_row_num + 1) {
+        Some((next_row_start_x, _)) => contents.get_prev_grapheme_boundary(*next_row_start_x, new_y).max(row_start_x),
+        None => new_line.len(),
+    };
+    cursor.y = new_y;
+    cursor.x = fns::get_x_at_column(&new_line, row_start_column + wanted_row_column, tab_width).clamp(row_start_x, row_max_x);
+    cursor.wanted_x = row_start_column + wanted_row_column;
+}
+
+
+
 pub fn move_cursor_left_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
     let mut cursor = &mut current_file.cursors[cursor_num];
     handle_cursor_selection_on_move(cursor, program_data);
     'main: {
         if cursor.x > 0 {
-            cursor.x -= 1;
+            cursor.x = current_file.contents.get_prev_grapheme_boundary(cursor.x, cursor.y);
         } else {
             if cursor.y == 0 {break 'main;}
             cursor.y -= 1;
-            cursor.x = current_file.contents[cursor.y].len();
+            cursor.x = current_file.contents.line_len(cursor.y);
         }
     }
-    cursor.wanted_x = cursor.x;
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
@@ -186,17 +974,17 @@ pub fn move_cursor_left_fn (current_file: &mut File, cursor_num: usize, program_
 pub fn move_cursor_right_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
     let mut cursor = &mut current_file.cursors[cursor_num];
     handle_cursor_selection_on_move(cursor, program_data);
-    let max_x = current_file.contents[cursor.y].len();
+    let max_x = current_file.contents.line_len(cursor.y);
     'main: {
         if cursor.x < max_x {
-            cursor.x += 1;
+            cursor.x = current_file.contents.get_next_grapheme_boundary(cursor.x, cursor.y);
         } else {
-            if cursor.y == current_file.contents.len() - 1 {break 'main;}
+            if cursor.y == current_file.contents.len_lines() - 1 {break 'main;}
             cursor.y += 1;
             cursor.x = 0;
         }
     }
-    cursor.wanted_x = cursor.x;
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
@@ -205,14 +993,134 @@ pub fn move_cursor_right_fn (current_file: &mut File, cursor_num: usize, program
 pub fn move_cursor_end_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
     let mut cursor = &mut current_file.cursors[cursor_num];
     handle_cursor_selection_on_move(cursor, program_data);
-    let max_x = current_file.contents[cursor.y].len();
+    let max_x = current_file.contents.line_len(cursor.y);
     cursor.x = max_x;
-    cursor.wanted_x = cursor.x;
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+    Ok(())
+}
+
+
+
+// at the start or end of a line, these move to the next line like the arrow keys do
+
+pub fn move_cursor_word_left_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &mut current_file.cursors[cursor_num];
+    if cursor.x == 0 {return move_cursor_left_fn(current_file, cursor_num, program_data);}
+    handle_cursor_selection_on_move(cursor, program_data);
+    cursor.x = fns::get_prev_word_boundary(&current_file.contents.line(cursor.y), cursor.x);
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+    Ok(())
+}
+
+pub fn move_cursor_word_right_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &mut current_file.cursors[cursor_num];
+    if cursor.x == current_file.contents.line_len(cursor.y) {return move_cursor_right_fn(current_file, cursor_num, program_data);}
+    handle_cursor_selection_on_move(cursor, program_data);
+    cursor.x = fns::get_next_word_boundary(&current_file.contents.line(cursor.y), cursor.x);
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+    Ok(())
+}
+
+
+
+// goes to the first non-whitespace char, or to the start of the line if it's already there
+pub fn move_cursor_home_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &mut current_file.cursors[cursor_num];
+    handle_cursor_selection_on_move(cursor, program_data);
+    let indent_len = current_file.contents.line_chars(cursor.y).take_while(|char| char.is_whitespace()).count();
+    cursor.x = if cursor.x == indent_len {0} else {indent_len};
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+    Ok(())
+}
+
+
+
+pub fn move_cursor_file_start_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &mut current_file.cursors[cursor_num];
+    handle_cursor_selection_on_move(cursor, program_data);
+    (cursor.x, cursor.y, cursor.wanted_x) = (0, 0, 0);
+    Ok(())
+}
+
+pub fn move_cursor_file_end_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &mut current_file.cursors[cursor_num];
+    handle_cursor_selection_on_move(cursor, program_data);
+    (cursor.x, cursor.y) = current_file.contents.end_pos();
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
 
 
+// the view scrolls by the same amount as the cursors, so they stay at the same place on the screen
+pub fn move_page_fn (move_up: bool, program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let page_len = match *program_data.text_section.read() {
+        Some(text_section) => {
+            let settings = program_data.settings.read();
+            render::get_visible_line_count(&text_section, settings.as_ref().expect("Error: settings is none")).max(1)
+        }
+        None => 1,
+    };
+    let max_scroll_y = current_file.contents.len_lines() - 1;
+    current_file.scroll_y = if move_up {
+        (current_file.scroll_y as usize).saturating_sub(page_len) as f64
+    } else {
+        (current_file.scroll_y as usize + page_len).min(max_scroll_y) as f64
+    };
+    let move_cursor_fn = |file: &mut File, cursor_num: usize, program_data: &ProgramData| {
+        let cursor = &mut file.cursors[cursor_num];
+        handle_cursor_selection_on_move(cursor, program_data);
+        cursor.y = if move_up {cursor.y.saturating_sub(page_len)} else {(cursor.y + page_len).min(max_scroll_y)};
+        cursor.x = file.contents.get_x_at_column(cursor.wanted_x, cursor.y, file.tab_width);
+        Ok(())
+    };
+    run_fn_at_cursors(move_cursor_fn, program_data, current_file)
+}
+
+
+
+// the cursor stays on the same side of the bracket, so jumping twice goes back to where it started
+pub fn jump_to_matching_bracket_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let search_area = get_cursors_bracket_search_area(current_file, program_data);
+    run_fn_at_cursors(|current_file, cursor_num, program_data| {
+        let cursor = &mut current_file.cursors[cursor_num];
+        let Some((bracket_pos, match_pos)) = search_area.find_matching_bracket(cursor.x, cursor.y) else {return Ok(());};
+        handle_cursor_selection_on_move(cursor, program_data);
+        cursor.x = match_pos.0 + (cursor.x - bracket_pos.0);
+        cursor.y = match_pos.1;
+        cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+        Ok(())
+    }, program_data, current_file)
+}
+
+
+
+// selects everything inside the brackets next to the cursor, or else inside the closest ones around it
+pub fn select_between_brackets_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let search_area = get_cursors_bracket_search_area(current_file, program_data);
+    run_fn_at_cursors(|current_file, cursor_num, _program_data| {
+        let cursor = &mut current_file.cursors[cursor_num];
+        let Some((opener_pos, closer_pos)) = search_area.find_enclosing_brackets(cursor.x, cursor.y) else {return Ok(());};
+        cursor.selection_start = Some((opener_pos.0 + 1, opener_pos.1));
+        (cursor.x, cursor.y) = closer_pos;
+        cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+        Ok(())
+    }, program_data, current_file)
+}
+
+
+
+// one search area covers every cursor, so the lines between them are only scanned once
+pub fn get_cursors_bracket_search_area (current_file: &File, program_data: &ProgramData) -> fns::BracketSearchArea {
+    let settings = program_data.settings.read();
+    let language = settings.as_ref().expect("Error: settings is none").get_known_language(&current_file.path);
+    let first_y = current_file.cursors.iter().map(|cursor| cursor.y).min().unwrap_or(0);
+    let last_y = current_file.cursors.iter().map(|cursor| cursor.y).max().unwrap_or(0);
+    fns::BracketSearchArea::new(&current_file.contents, first_y, last_y, language)
+}
+
+
+
 pub fn handle_cursor_selection_on_move (cursor: &mut Cursor, program_data: &ProgramData) {
     if program_data.keys_pressed.read().shift_pressed {
         if cursor.selection_start.is_none() {
@@ -227,81 +1135,420 @@ pub fn handle_cursor_selection_on_move (cursor: &mut Cursor, program_data: &Prog
 
 
 
-pub fn backspace_fn (current_file: &mut File, cursor_num: usize, _program_data: &ProgramData) -> Result<(), ProgramError> {
-    let mut cursor = &mut current_file.cursors[cursor_num];
-    let contents = &mut current_file.contents;
+pub fn backspace_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &current_file.cursors[cursor_num];
+    let (x, y) = (cursor.x, cursor.y);
     'main: {
 
         if cursor.selection_start.is_some() {
-            delete_selected_area(contents, cursor);
+            delete_selected_area(current_file, cursor_num);
             break 'main;
         }
 
-        if cursor.x == 0 {
-            if cursor.y == 0 {return Ok(());}
-            let bottom_line = &mut contents.remove(cursor.y);
-            cursor.y -= 1;
-            cursor.x = contents[cursor.y].len();
-            contents[cursor.y].append(bottom_line);
+        if x == 0 {
+            if y == 0 {return Ok(());}
+            let new_x = current_file.contents.line_len(y - 1);
+            current_file.remove_text((new_x, y - 1), (0, y));
             break 'main;
         }
 
-        let current_line = &mut contents[cursor.y];
-        current_line.remove(cursor.x - 1);
-        cursor.x -= 1;
+        // empty pairs like "(|)" get deleted together
+        let settings = program_data.settings.read();
+        let language = settings.as_ref().expect("Error: settings is none").get_language(&current_file.path);
+        let line = current_file.contents.line(y);
+        if let (Some(prev_char), Some(next_char)) = (line.get(x - 1), line.get(x)) {
+            if language.get_closer(*prev_char) == Some(*next_char) {
+                current_file.remove_text((x - 1, y), (x + 1, y));
+                break 'main;
+            }
+        }
+
+        let new_x = current_file.contents.get_prev_grapheme_boundary(x, y);
+        current_file.remove_text((new_x, y), (x, y));
 
     }
-    cursor.wanted_x = cursor.x;
+    let cursor = &mut current_file.cursors[cursor_num];
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
 
 
 pub fn delete_fn (current_file: &mut File, cursor_num: usize, _program_data: &ProgramData) -> Result<(), ProgramError> {
-    let mut cursor = &mut current_file.cursors[cursor_num];
-    let contents = &mut current_file.contents;
-    let current_line = &mut contents[cursor.y];
+    let cursor = &current_file.cursors[cursor_num];
+    let (x, y) = (cursor.x, cursor.y);
 
     if cursor.selection_start.is_some() {
-        delete_selected_area(contents, cursor);
-        cursor.wanted_x = cursor.x;
+        delete_selected_area(current_file, cursor_num);
         return Ok(());
     }
 
-    if cursor.x == current_line.len() {
-        if cursor.y == contents.len() - 1 {return Ok(());}
-        let next_line = &mut contents.remove(cursor.y + 1);
-        contents[cursor.y].append(next_line);
+    if x == current_file.contents.line_len(y) {
+        if y == current_file.contents.len_lines() - 1 {return Ok(());}
+        current_file.remove_text((x, y), (0, y + 1));
         return Ok(());
     }
 
-    current_line.remove(cursor.x);
-    cursor.wanted_x = cursor.x;
+    let grapheme_end = current_file.contents.get_next_grapheme_boundary(x, y);
+    current_file.remove_text((x, y), (grapheme_end, y));
+    current_file.cursors[cursor_num].wanted_x = current_file.contents.get_column(x, y, current_file.tab_width);
+    Ok(())
+}
+
+
+
+// these delete to the next word boundary, or the line break if the cursor is at the start or end of a line
+
+pub fn delete_word_before_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &current_file.cursors[cursor_num];
+    let (x, y) = (cursor.x, cursor.y);
+    if cursor.selection_start.is_some() || x == 0 {return backspace_fn(current_file, cursor_num, program_data);}
+    let word_start = fns::get_prev_word_boundary(&current_file.contents.line(y), x);
+    current_file.remove_text((word_start, y), (x, y));
     Ok(())
 }
 
+pub fn delete_word_after_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &current_file.cursors[cursor_num];
+    let (x, y) = (cursor.x, cursor.y);
+    if cursor.selection_start.is_some() || x == current_file.contents.line_len(y) {return delete_fn(current_file, cursor_num, program_data);}
+    let word_end = fns::get_next_word_boundary(&current_file.contents.line(y), x);
+    current_file.remove_text((x, y), (word_end, y));
+    Ok(())
+}
 
 
+
+// the new line keeps the indentation of the current line, plus one level after an opening bracket or ':'
 pub fn return_fn (current_file: &mut File, cursor_num: usize, _program_data: &ProgramData) -> Result<(), ProgramError> {
-    let mut cursor = &mut current_file.cursors[cursor_num];
-    let contents = &mut current_file.contents;
-    let current_line = &mut contents[cursor.y];
-    
-    let new_line = current_line.split_off(cursor.x);
-    contents.insert(cursor.y + 1, new_line);
+    delete_selected_area(current_file, cursor_num);
+    let cursor = &current_file.cursors[cursor_num];
+    let (x, y) = (cursor.x, cursor.y);
+    let line = current_file.contents.line(y);
+
+    let indent = line[..x].iter().take_while(|char| **char == ' ' || **char == '\t').collect::<String>();
+    let last_char = line[..x].iter().rev().find(|char| !char.is_whitespace()).copied();
+    let next_char = line.get(x).copied();
+    let new_line_indent = match (last_char, current_file.indentation) {
+        (Some('{' | '(' | '[' | ':'), Indentation::Tabs) => format!("{indent}\t"),
+        (Some('{' | '(' | '[' | ':'), Indentation::Spaces(width)) => format!("{indent}{}", " ".repeat(width)),
+        _ => indent.to_string(),
+    };
 
-    cursor.y += 1;
-    cursor.x = 0;
-    cursor.wanted_x = cursor.x;
+    // "{|}" gets split into three lines, with the cursor on the middle one
+    let new_text = match (last_char, next_char) {
+        (Some('{'), Some('}')) | (Some('('), Some(')')) | (Some('['), Some(']')) => format!("\n{new_line_indent}\n{indent}"),
+        _ => format!("\n{new_line_indent}"),
+    };
+    current_file.insert_text(x, y, &new_text);
+
+    let cursor = &mut current_file.cursors[cursor_num];
+    (cursor.x, cursor.y) = (new_line_indent.chars().count(), y + 1);
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
     Ok(())
 }
 
 
 
-pub fn delete_selected_area (contents: &mut [Vec<char>], cursor: &Cursor) {
-    let (start_x, start_y) = cursor.selection_start.unwrap();
-    let (end_x, end_y) = (cursor.x, cursor.y);
-    println!("WIP: delete area between ({start_x}, {start_y}) and ({end_x}, {end_y})");
+// inserts indentation at the cursor, or indents every selected line if there's a selection
+pub fn tab_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let cursor = &current_file.cursors[cursor_num];
+    if cursor.selection_start.is_some() {
+        return indent_lines_fn(current_file, cursor_num, program_data);
+    }
+    let (x, y) = (cursor.x, cursor.y);
+
+    // soft tabs go to the next multiple of the indent width
+    let indent = match current_file.indentation {
+        Indentation::Tabs => String::from("\t"),
+        Indentation::Spaces(width) => {
+            let column = current_file.contents.get_column(x, y, current_file.tab_width);
+            " ".repeat(width - column % width)
+        }
+    };
+    current_file.insert_text(x, y, &indent);
+    Ok(())
+}
+
+
+
+pub fn indent_lines_fn (current_file: &mut File, cursor_num: usize, _program_data: &ProgramData) -> Result<(), ProgramError> {
+    let indent = match current_file.indentation {
+        Indentation::Tabs => String::from("\t"),
+        Indentation::Spaces(width) => " ".repeat(width),
+    };
+    for y in get_unhandled_cursor_lines(&current_file.cursors, cursor_num) {
+        if current_file.contents.line_len(y) == 0 {continue;}
+        current_file.insert_text(0, y, &indent);
+    }
+    Ok(())
+}
+
+
+
+pub fn outdent_lines_fn (current_file: &mut File, cursor_num: usize, _program_data: &ProgramData) -> Result<(), ProgramError> {
+    let indent_width = current_file.indentation.get_width(current_file.tab_width);
+    for y in get_unhandled_cursor_lines(&current_file.cursors, cursor_num) {
+        let line = current_file.contents.line(y);
+        let removed_len = match line.first() {
+            Some('\t') => 1,
+            _ => line.iter().take(indent_width).take_while(|char| **char == ' ').count(),
+        };
+        if removed_len == 0 {continue;}
+        current_file.remove_text((0, y), (removed_len, y));
+    }
+    Ok(())
+}
+
+
+
+pub fn toggle_line_comments_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let settings = program_data.settings.read();
+    let line_comment = settings.as_ref().expect("Error: settings is none").get_language(&current_file.path).line_comment.clone();
+    drop(settings);
+    let Some(line_comment) = line_comment else {
+        println!("Warning: there are no line comments for file {}", current_file.path);
+        return Ok(());
+    };
+    run_line_fn_at_cursors(|file, first_y, last_y, _group| toggle_line_comments(file, first_y, last_y, &line_comment), program_data, current_file)
+}
+
+// if any line isn't commented then they all get commented, and blank lines are skipped unless every line is blank
+pub fn toggle_line_comments (current_file: &mut File, first_y: usize, last_y: usize, line_comment: &str) {
+    let comment_chars = line_comment.chars().collect::<Vec<char>>();
+    let lines = (first_y..=last_y)
+        .map(|y| (y, current_file.contents.line(y)))
+        .collect::<Vec<(usize, Vec<char>)>>();
+    let get_indent_len = |line: &[char]| line.iter().take_while(|char| char.is_whitespace()).count();
+    let mut changed_lines = lines.iter().filter(|(_, line)| get_indent_len(line) < line.len()).collect::<Vec<_>>();
+    if changed_lines.is_empty() {changed_lines = lines.iter().collect();}
+
+    let all_commented = changed_lines.iter().all(|(_, line)| line[get_indent_len(line)..].starts_with(&comment_chars));
+    if all_commented {
+        for (y, line) in changed_lines {
+            let comment_x = get_indent_len(line);
+            let has_space = line.get(comment_x + comment_chars.len()) == Some(&' ');
+            let removed_len = comment_chars.len() + has_space as usize;
+            current_file.remove_text((comment_x, *y), (comment_x + removed_len, *y));
+        }
+    } else {
+        // the comments all line up at the smallest indentation
+        let comment_x = changed_lines.iter().map(|(_, line)| get_indent_len(line)).min().unwrap_or(0);
+        let comment = format!("{line_comment} ");
+        for (y, _) in changed_lines {
+            current_file.insert_text(comment_x, *y, &comment);
+        }
+    }
+}
+
+
+
+pub fn toggle_block_comment_fn (program_data: &ProgramData, current_file: &mut File) -> Result<(), ProgramError> {
+    let settings = program_data.settings.read();
+    let block_comment = settings.as_ref().expect("Error: settings is none").get_language(&current_file.path).block_comment.clone();
+    drop(settings);
+    let Some((comment_start, comment_end)) = block_comment else {
+        println!("Warning: there are no block comments for file {}", current_file.path);
+        return Ok(());
+    };
+    run_line_fn_at_cursors(|file, first_y, last_y, group| toggle_block_comment(file, first_y, last_y, group, &comment_start, &comment_end), program_data, current_file)
+}
+
+// a lone cursor comments out its selection, but cursors that share lines comment out all of their lines together so they don't undo each other
+pub fn toggle_block_comment (current_file: &mut File, first_y: usize, last_y: usize, group: &[usize], comment_start: &str, comment_end: &str) {
+    let lone_cursor_num = match group {
+        [cursor_num] => Some(*cursor_num),
+        _ => None,
+    };
+    let selection_range = lone_cursor_num.and_then(|cursor_num| get_selection_range(&current_file.cursors[cursor_num]));
+    let (start, end) = selection_range.unwrap_or_else(|| {
+        let indent_len = current_file.contents.line_chars(first_y).take_while(|char| char.is_whitespace()).count();
+        ((indent_len, first_y), (current_file.contents.line_len(last_y), last_y))
+    });
+
+    let text = current_file.contents.slice(start, end);
+    let trimmed_text = text.trim();
+    let is_commented = trimmed_text.len() >= comment_start.len() + comment_end.len() && trimmed_text.starts_with(comment_start) && trimmed_text.ends_with(comment_end);
+    if is_commented {
+        let comment_start_pos = fns::get_text_end_pos(start, &text[..text.len() - text.trim_start().len()]);
+        let comment_end_pos = fns::get_text_end_pos(start, text.trim_end());
+        let inner_text = &trimmed_text[comment_start.len() .. trimmed_text.len() - comment_end.len()];
+        let has_start_space = inner_text.starts_with(' ');
+        let has_end_space = inner_text.len() > 1 && inner_text.ends_with(' ');
+
+        let removed_len = comment_end.chars().count() + has_end_space as usize;
+        let removed_x = comment_end_pos.0 - removed_len;
+        current_file.remove_text((removed_x, comment_end_pos.1), comment_end_pos);
+        let removed_len = comment_start.chars().count() + has_start_space as usize;
+        current_file.remove_text(comment_start_pos, (comment_start_pos.0 + removed_len, comment_start_pos.1));
+    } else {
+        let comment_end = format!(" {comment_end}");
+        current_file.insert_text(end.0, end.1, &comment_end);
+        let comment_start = format!("{comment_start} ");
+        current_file.insert_text(start.0, start.1, &comment_start);
+
+        // the selection is extended to cover the whole comment, and an empty comment puts the cursor inside it
+        let Some(cursor_num) = lone_cursor_num else {return;};
+        let cursor = &mut current_file.cursors[cursor_num];
+        match &mut cursor.selection_start {
+            Some(selection_start) if (selection_start.1, selection_start.0) < (cursor.y, cursor.x) => *selection_start = start,
+            Some(_) => (cursor.x, cursor.y) = start,
+            None if start == end => cursor.x = start.0 + comment_start.chars().count(),
+            None => {}
+        }
+        cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+    }
+}
+
+
+
+// rewraps every paragraph in the selection, or the paragraph that the cursor is in
+pub fn reflow_fn (current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let settings = program_data.settings.read();
+    let settings = settings.as_ref().expect("Error: settings is none");
+    let language = settings.get_language(&current_file.path);
+    let cursor = &current_file.cursors[cursor_num];
+    let (first_y, last_y) = match cursor.selection_start {
+        Some(_) => get_cursor_lines(cursor),
+        None => match get_paragraph_lines(&current_file.contents, cursor.y, language) {
+            Some(paragraph_lines) => paragraph_lines,
+            None => return Ok(()),
+        }
+    };
+
+    let mut paragraphs = vec!();
+    let mut paragraph_start = None;
+    for y in first_y..=last_y {
+        let line = current_file.contents.line(y);
+        let is_blank = fns::is_line_blank(&line, language);
+        let is_list_item = fns::is_list_item(&line, language);
+        match (is_blank, paragraph_start) {
+            (false, Some(start_y)) if is_list_item => {
+                paragraphs.push((start_y, y - 1));
+                paragraph_start = Some(y);
+            }
+            (false, None) => paragraph_start = Some(y),
+            (true, Some(start_y)) => {
+                paragraphs.push((start_y, y - 1));
+                paragraph_start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start_y) = paragraph_start {paragraphs.push((start_y, last_y));}
+
+    // this goes from the bottom up so that the line numbers of the other paragraphs don't change
+    for (start_y, end_y) in paragraphs.into_iter().rev() {
+        reflow_paragraph(current_file, start_y, end_y, settings.reflow_column, language);
+    }
+    Ok(())
+}
+
+// paragraphs end at blank lines and before list items
+pub fn get_paragraph_lines (contents: &TextBuffer, y: usize, language: &LanguageSettings) -> Option<(usize, usize)> {
+    let is_blank = |y: usize| fns::is_line_blank(&contents.line(y), language);
+    let is_list_item = |y: usize| fns::is_list_item(&contents.line(y), language);
+    if is_blank(y) {return None;}
+    let mut first_y = y;
+    while first_y > 0 && !is_list_item(first_y) && !is_blank(first_y - 1) {first_y -= 1;}
+    let mut last_y = y;
+    while last_y + 1 < contents.len_lines() && !is_blank(last_y + 1) && !is_list_item(last_y + 1) {last_y += 1;}
+    Some((first_y, last_y))
+}
+
+// cursors in the paragraph stay next to the same word, and cursors after it move with the lines
+pub fn reflow_paragraph (current_file: &mut File, first_y: usize, last_y: usize, reflow_column: usize, language: &LanguageSettings) {
+    let old_lines = (first_y..=last_y).map(|y| current_file.contents.line(y)).collect::<Vec<Vec<char>>>();
+    let new_lines = fns::reflow_lines(&old_lines, reflow_column, current_file.tab_width, language);
+    let new_text = new_lines.join("\n");
+    if current_file.contents.slice((0, first_y), (old_lines[old_lines.len() - 1].len(), last_y)) == new_text {return;}
+
+    let get_offset = |(x, y): (usize, usize)| fns::some_if((first_y..=last_y).contains(&y), || get_content_offset(&old_lines, x, y - first_y, language));
+    let cursor_offsets = current_file.cursors.iter()
+        .map(|cursor| (get_offset((cursor.x, cursor.y)), cursor.selection_start.and_then(get_offset)))
+        .collect::<Vec<(Option<usize>, Option<usize>)>>();
+
+    current_file.remove_text((0, first_y), (old_lines[old_lines.len() - 1].len(), last_y));
+    current_file.insert_text(0, first_y, &new_text);
+
+    // cursors after the paragraph were already moved by the edit
+    let new_lines = new_lines.iter().map(|line| line.chars().collect()).collect::<Vec<Vec<char>>>();
+    let get_new_pos = |offset: usize| {
+        let (new_x, new_y) = get_pos_at_content_offset(&new_lines, offset, language);
+        (new_x, new_y + first_y)
+    };
+    for (cursor, (offset, selection_offset)) in current_file.cursors.iter_mut().zip(cursor_offsets) {
+        if let Some(offset) = offset {(cursor.x, cursor.y) = get_new_pos(offset);}
+        if let Some(selection_offset) = selection_offset {cursor.selection_start = Some(get_new_pos(selection_offset));}
+        cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+    }
+}
+
+// the number of non-whitespace chars before the position, not counting line prefixes
+pub fn get_content_offset (lines: &[Vec<char>], x: usize, y: usize, language: &LanguageSettings) -> usize {
+    let count_content = |line: &[char]| line.iter().filter(|char| !char.is_whitespace()).count();
+    let lines_before = lines[..y].iter().map(|line| count_content(&line[fns::get_line_prefix_len(line, language)..])).sum::<usize>();
+    let prefix_len = fns::get_line_prefix_len(&lines[y], language);
+    lines_before + count_content(&lines[y][prefix_len.min(x)..x])
+}
+
+pub fn get_pos_at_content_offset (lines: &[Vec<char>], offset: usize, language: &LanguageSettings) -> (usize, usize) {
+    if offset == 0 {return (fns::get_line_prefix_len(&lines[0], language), 0);}
+    let mut remaining = offset;
+    for (y, line) in lines.iter().enumerate() {
+        for (x, char) in line.iter().enumerate().skip(fns::get_line_prefix_len(line, language)) {
+            if char.is_whitespace() {continue;}
+            remaining -= 1;
+            if remaining == 0 {return (x + 1, y);}
+        }
+    }
+    (lines[lines.len() - 1].len(), lines.len() - 1)
+}
+
+
+
+// returns the start and end of the selection in the order they are in the text
+pub fn get_selection_range (cursor: &Cursor) -> Option<((usize, usize), (usize, usize))> {
+    let selection_start = cursor.selection_start?;
+    let cursor_pos = (cursor.x, cursor.y);
+    if (selection_start.1, selection_start.0) < (cursor.y, cursor.x) {
+        Some((selection_start, cursor_pos))
+    } else {
+        Some((cursor_pos, selection_start))
+    }
+}
+
+// like get_selection_range, but a cursor without a selection gives an empty range at the cursor
+pub fn get_cursor_range (cursor: &Cursor) -> ((usize, usize), (usize, usize)) {
+    get_selection_range(cursor).unwrap_or(((cursor.x, cursor.y), (cursor.x, cursor.y)))
+}
+
+// a selection that ends at the start of a line doesn't include that line
+pub fn get_cursor_lines (cursor: &Cursor) -> (usize, usize) {
+    let Some((selection_x, selection_y)) = cursor.selection_start else {return (cursor.y, cursor.y);};
+    let (first_y, last_y) = (selection_y.min(cursor.y), selection_y.max(cursor.y));
+    let last_x = if cursor.y > selection_y {cursor.x} else {selection_x};
+    if last_y > first_y && last_x == 0 {(first_y, last_y - 1)} else {(first_y, last_y)}
+}
+
+// skips lines that are also covered by an earlier cursor, so every line only gets changed once
+pub fn get_unhandled_cursor_lines (cursors: &[Cursor], cursor_num: usize) -> Vec<usize> {
+    let (first_y, last_y) = get_cursor_lines(&cursors[cursor_num]);
+    (first_y..=last_y)
+        .filter(|y| !cursors[..cursor_num].iter().any(|cursor| {
+            let (other_first_y, other_last_y) = get_cursor_lines(cursor);
+            (other_first_y..=other_last_y).contains(y)
+        }))
+        .collect()
+}
+
+
+
+// the cursor ends up where the selection started
+pub fn delete_selected_area (current_file: &mut File, cursor_num: usize) {
+    let Some((start, end)) = get_selection_range(&current_file.cursors[cursor_num]) else {return;};
+    current_file.remove_text(start, end);
+    current_file.cursors[cursor_num].selection_start = None;
 }
 
 
@@ -310,15 +1557,234 @@ pub fn delete_selected_area (contents: &mut [Vec<char>], cursor: &Cursor) {
 
 fn handle_text_input (text: &str, program_data: &ProgramData, current_file: Option<&mut File>, timestamp: u32) -> Result<(), ProgramError> {
     let Some(current_file) = current_file else {return Ok(());};
-    let place_text_fn = |file: &mut File, cursor_num: usize, _program_data: &ProgramData| {
-        let cursor: &mut Cursor = &mut file.cursors[cursor_num];
-        let current_line = &mut file.contents[cursor.y];
-        let text_chars = text.chars().collect::<Vec<char>>();
-        fns::insert_all(&text_chars, current_line, cursor.x);
+    if let Some(hex_view) = &mut current_file.hex_view {
+        for digit in text.chars().filter_map(|char| char.to_digit(16)) {
+            hex_view.type_hex_digit(digit as u8);
+        }
+        *program_data.cursor_place_instant.write() = Instant::now();
+        *program_data.last_text_input_timestamp.write() = timestamp;
+        return Ok(());
+    }
+    let place_text_fn = |file: &mut File, cursor_num: usize, program_data: &ProgramData| type_text_fn(text, file, cursor_num, program_data);
+    run_edit_fn_at_cursors(place_text_fn, EditKind::Typing, program_data, current_file)?;
+    *program_data.last_text_input_timestamp.write() = timestamp;
+    Ok(())
+}
+
+
+
+// single typed characters are checked against the language's auto pairs, so that brackets and quotes get closed automatically
+pub fn type_text_fn (text: &str, current_file: &mut File, cursor_num: usize, program_data: &ProgramData) -> Result<(), ProgramError> {
+    let settings = program_data.settings.read();
+    let language = settings.as_ref().expect("Error: settings is none").get_language(&current_file.path);
+    let mut chars = text.chars();
+    let typed_char = match (chars.next(), chars.next()) {
+        (Some(typed_char), None) => Some(typed_char),
+        _ => None,
+    };
+    let closer = typed_char.and_then(|typed_char| language.get_closer(typed_char));
+    // a selection gets replaced by the typed text, unless it's about to be wrapped in a pair
+    if closer.is_none() {
+        delete_selected_area(current_file, cursor_num);
+    }
+
+    let cursor = &current_file.cursors[cursor_num];
+    let (x, y) = (cursor.x, cursor.y);
+    let Some(typed_char) = typed_char else {
+        current_file.insert_text(x, y, text);
+        return Ok(());
+    };
+    let line = current_file.contents.line(y);
+    let prev_char = x.checked_sub(1).and_then(|prev_x| line.get(prev_x)).copied();
+    let next_char = line.get(x).copied();
+
+    // wrap the selection
+    if let (Some(closer), Some(selection_start)) = (closer, cursor.selection_start) {
+        let cursor_is_at_end = (selection_start.1, selection_start.0) < (y, x);
+        let (start, end) = if cursor_is_at_end {(selection_start, (x, y))} else {((x, y), selection_start)};
+        current_file.insert_text(end.0, end.1, &closer.to_string());
+        current_file.insert_text(start.0, start.1, &typed_char.to_string());
+        let new_start = (start.0 + 1, start.1);
+        let new_end = (if end.1 == start.1 {end.0 + 1} else {end.0}, end.1);
+        let (new_cursor_pos, new_selection_start) = if cursor_is_at_end {(new_end, new_start)} else {(new_start, new_end)};
+        let cursor = &mut current_file.cursors[cursor_num];
+        (cursor.x, cursor.y) = new_cursor_pos;
+        cursor.selection_start = Some(new_selection_start);
+        cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+        return Ok(());
+    }
+
+    // step over a closer that's already there
+    if cursor.selection_start.is_none() && next_char == Some(typed_char) && language.is_closer(typed_char) {
+        let cursor = &mut current_file.cursors[cursor_num];
         cursor.x += 1;
-        Ok(())
+        cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
+        return Ok(());
+    }
+
+    // quotes aren't paired right after a word, so things like "don't" can still be typed
+    let is_after_word = prev_char.is_some_and(fns::is_word_char);
+    let is_before_space = next_char.is_none_or(|char| char.is_whitespace() || language.is_closer(char));
+    let new_text = match closer {
+        Some(closer) if is_before_space && !(closer == typed_char && is_after_word) => format!("{typed_char}{closer}"),
+        _ => typed_char.to_string(),
     };
-    run_fn_at_cursors(place_text_fn, program_data, current_file)?;
-    *program_data.last_text_input_timestamp.write() = timestamp;
+    current_file.insert_text(x, y, &new_text);
+    // the cursor goes between the pair
+    let cursor = &mut current_file.cursors[cursor_num];
+    cursor.x = x + 1;
+    cursor.wanted_x = current_file.contents.get_column(cursor.x, cursor.y, current_file.tab_width);
     Ok(())
 }
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_file (text: &str, cursors: &[(usize, usize)]) -> File {
+        let mut file = File::new(String::from("test.c"), TextBuffer::from_text(text));
+        file.cursors = cursors.iter().map(|(x, y)| Cursor {x: *x, y: *y, wanted_x: *x, selection_start: None}).collect();
+        file
+    }
+
+    // the same as run_line_fn_at_cursors, without the history and program data
+    fn run_line_fn (file: &mut File, line_fn: impl Fn(&mut File, usize, usize, &[usize])) {
+        for (first_y, last_y, group) in get_line_groups(&file.cursors).into_iter().rev() {
+            line_fn(file, first_y, last_y, &group);
+        }
+    }
+
+    fn toggle_block_comments (file: &mut File) {
+        run_line_fn(file, |file, first_y, last_y, group| toggle_block_comment(file, first_y, last_y, group, "/*", "*/"));
+    }
+
+    fn get_cursor_ys (file: &File) -> Vec<usize> {
+        file.cursors.iter().map(|cursor| cursor.y).collect()
+    }
+
+    fn new_cursor (x: usize, selection_x: Option<usize>) -> Cursor {
+        Cursor {x, y: 0, wanted_x: x, selection_start: selection_x.map(|selection_x| (selection_x, 0))}
+    }
+
+    #[test]
+    fn overlapping_cursors_are_merged () {
+        let mut cursors = vec!(new_cursor(5, Some(2)), new_cursor(9, None), new_cursor(3, None), new_cursor(4, Some(7)), new_cursor(9, None), new_cursor(7, None));
+        remove_cursor_duplicates(&mut cursors);
+        // the selections from 2 to 5 and 4 to 7 merge, along with the cursor at 3 inside them, and the cursor at 7 only touches the end
+        let positions = cursors.iter().map(|cursor| (cursor.x, cursor.selection_start)).collect::<Vec<_>>();
+        assert_eq!(positions, vec!((2, Some((7, 0))), (9, None), (7, None)));
+    }
+
+    #[test]
+    fn separate_cursors_keep_their_order () {
+        let mut cursors = (0..1000).rev().map(|x| new_cursor(x * 2, Some(x * 2 + 1))).collect::<Vec<Cursor>>();
+        let old_cursors = cursors.clone();
+        remove_cursor_duplicates(&mut cursors);
+        assert_eq!(cursors, old_cursors);
+    }
+
+    #[test]
+    fn block_comments_with_cursors_on_the_same_line () {
+        let mut file = new_file("    a b c\nd", &[(5, 0), (7, 0)]);
+        toggle_block_comments(&mut file);
+        assert_eq!(file.contents.to_string(), "    /* a b c */\nd");
+        toggle_block_comments(&mut file);
+        assert_eq!(file.contents.to_string(), "    a b c\nd");
+    }
+
+    #[test]
+    fn block_comments_around_a_selection () {
+        let mut file = new_file("a b c", &[(3, 0)]);
+        file.cursors[0].selection_start = Some((2, 0));
+        toggle_block_comments(&mut file);
+        assert_eq!(file.contents.to_string(), "a /* b */ c");
+        assert_eq!(get_selection_range(&file.cursors[0]), Some(((2, 0), (9, 0))));
+    }
+
+    #[test]
+    fn edits_keep_the_column_of_cursors_on_other_lines () {
+        let mut file = new_file("abc\nabcdef", &[(1, 0), (2, 1)]);
+        file.cursors[1].wanted_x = 5;
+        file.insert_text(0, 0, "x");
+        assert_eq!((file.cursors[0].x, file.cursors[0].wanted_x), (2, 2));
+        assert_eq!((file.cursors[1].x, file.cursors[1].y, file.cursors[1].wanted_x), (2, 1, 5));
+        file.insert_text(0, 0, "\n");
+        assert_eq!((file.cursors[1].x, file.cursors[1].y, file.cursors[1].wanted_x), (2, 2, 5));
+    }
+
+    #[test]
+    fn line_groups_merge_overlapping_and_touching_lines () {
+        let mut file = new_file("", &[(0, 4), (0, 0), (0, 2), (0, 6), (0, 4)]);
+        file.cursors[1].selection_start = Some((2, 1));
+        // a selection that ends at the start of a line doesn't include that line
+        file.cursors[3].selection_start = Some((0, 7));
+        assert_eq!(get_line_groups(&file.cursors), vec!((0, 2, vec!(1, 2)), (4, 4, vec!(0, 4)), (6, 6, vec!(3))));
+    }
+
+    #[test]
+    fn move_lines_up_and_down () {
+        let mut file = new_file("a\nb\nc\nd\ne", &[(0, 1), (0, 2), (0, 4)]);
+        run_line_fn(&mut file, move_lines_up_fn);
+        assert_eq!(file.contents.to_string(), "b\nc\na\ne\nd");
+        assert_eq!(get_cursor_ys(&file), vec!(0, 1, 3));
+        // the lines at the top of the file can't move up, but the other group still does
+        run_line_fn(&mut file, move_lines_up_fn);
+        assert_eq!(file.contents.to_string(), "b\nc\ne\na\nd");
+        assert_eq!(get_cursor_ys(&file), vec!(0, 1, 2));
+
+        let mut file = new_file("a\nb\nc", &[(0, 0), (0, 2)]);
+        run_line_fn(&mut file, move_lines_down_fn);
+        assert_eq!(file.contents.to_string(), "b\na\nc");
+        assert_eq!(get_cursor_ys(&file), vec!(1, 2));
+
+        // the empty line after a trailing newline is a line like any other
+        let mut file = new_file("a\nb\n", &[(1, 1)]);
+        run_line_fn(&mut file, move_lines_down_fn);
+        assert_eq!(file.contents.to_string(), "a\n\nb");
+        assert_eq!(get_cursor_ys(&file), vec!(2));
+    }
+
+    #[test]
+    fn duplicate_lines () {
+        let mut file = new_file("a\nb\nc\nd", &[(0, 0), (0, 1), (1, 3)]);
+        run_line_fn(&mut file, duplicate_lines_fn);
+        assert_eq!(file.contents.to_string(), "a\nb\na\nb\nc\nd\nd");
+        assert_eq!(get_cursor_ys(&file), vec!(2, 3, 6));
+        assert_eq!(file.cursors[2].x, 1);
+    }
+
+    #[test]
+    fn delete_lines () {
+        let mut file = new_file("a\nb\nc\nd\ne", &[(1, 2), (0, 2), (0, 4)]);
+        file.cursors[0].selection_start = Some((0, 1));
+        run_line_fn(&mut file, delete_lines_fn);
+        assert_eq!(file.contents.to_string(), "a\nd");
+        assert_eq!(file.cursors.iter().map(|cursor| (cursor.x, cursor.y, cursor.selection_start)).collect::<Vec<_>>(), vec!((1, 1, None), (0, 1, None), (0, 1, None)));
+
+        let mut file = new_file("a\nb", &[(0, 0)]);
+        run_line_fn(&mut file, delete_lines_fn);
+        assert_eq!(file.contents.to_string(), "b");
+        run_line_fn(&mut file, delete_lines_fn);
+        assert_eq!(file.contents.to_string(), "");
+        assert_eq!(get_cursor_ys(&file), vec!(0));
+    }
+
+    #[test]
+    fn join_lines () {
+        let mut file = new_file("a\n  b\nc\nd", &[(0, 0), (0, 3)]);
+        run_line_fn(&mut file, join_lines_fn);
+        // the last line has nothing to be joined with
+        assert_eq!(file.contents.to_string(), "a b\nc\nd");
+        assert_eq!((file.cursors[0].x, file.cursors[0].y), (1, 0));
+        assert_eq!(get_cursor_ys(&file), vec!(0, 2));
+
+        let mut file = new_file("a\nb\nc", &[(1, 2)]);
+        file.cursors[0].selection_start = Some((0, 0));
+        run_line_fn(&mut file, join_lines_fn);
+        assert_eq!(file.contents.to_string(), "a b c");
+        assert_eq!((file.cursors[0].x, file.cursors[0].y, file.cursors[0].selection_start), (3, 0, None));
+    }
+
+}
@@ -7,6 +7,7 @@ use crate::prelude::*;
 pub fn update (program_data: &ProgramData, event_pump: &mut EventPump) -> Result<(), ProgramError> {
 
     handle_events(program_data, event_pump)?;
+    events::update_mouse_drag(program_data)?;
 
     let errors = program_data.errors.read();
     for error in errors.iter() {